@@ -0,0 +1,266 @@
+// 只能加不能删的bloom filter，给skiplist挡掉查不到的key
+// 删除多了以后只能整个重建
+
+use std::hash::{Hash, Hasher};
+
+// 序列化格式的版本，hash算法或者布局变了就要加一，旧的filter读回来直接拒绝
+const MAGIC: &[u8; 4] = b"BLMF";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 28;
+// new()算出来的hash次数不会超过这个，读回来的也不能超过，不然一次查询要循环几十亿次
+const MAX_HASHES: u32 = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: usize,
+    num_hashes: u32,
+    items: usize,
+}
+
+// filter会跟着刷盘的数据一起序列化，换了编译器版本以后也得算出一样的hash，
+// 所以不能用DefaultHasher(算法不保证稳定)，用固定的64位FNV-1a
+pub fn hash<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = FnvHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+// 64位FNV-1a: http://www.isthe.com/chongo/tech/comp/fnv/
+// 整数一律按小端、usize按u64喂进去，大小端和32/64位机器上结果都一样
+// str、切片这些由std的Hash实现决定怎么喂字节，stable_hash_test里钉住了，变了会直接挂
+pub struct FnvHasher(u64);
+
+impl FnvHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    pub fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16)
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32)
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128)
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64)
+    }
+}
+
+impl BloomFilter {
+    // 按预计元素个数和假阳性率算位数和hash次数
+    // m = -n * ln(p) / ln(2)^2, k = m / n * ln(2)
+    pub fn new(expected_items: usize, fp_rate: f64) -> Self {
+        let n = expected_items.max(1) as f64;
+        let p = fp_rate.clamp(1e-9, 0.5);
+        let num_bits = ((-n * p.ln() / (2f64.ln() * 2f64.ln())).ceil() as usize).max(64);
+        let num_hashes = ((num_bits as f64 / n * 2f64.ln()).round() as u32).clamp(1, MAX_HASHES);
+        Self {
+            bits: vec![0; num_bits.div_ceil(64)],
+            num_bits,
+            num_hashes,
+            items: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.iter_mut().for_each(|w| *w = 0);
+        self.items = 0;
+    }
+
+    pub fn insert<K: Hash + ?Sized>(&mut self, key: &K) {
+        self.insert_hash(hash(key))
+    }
+
+    pub fn may_contain<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        self.may_contain_hash(hash(key))
+    }
+
+    pub fn insert_hash(&mut self, h: u64) {
+        for idx in self.indexes(h) {
+            self.bits[idx / 64] |= 1 << (idx % 64);
+        }
+        self.items += 1;
+    }
+
+    pub fn may_contain_hash(&self, h: u64) -> bool {
+        self.indexes(h)
+            .all(|idx| self.bits[idx / 64] & (1 << (idx % 64)) != 0)
+    }
+
+    // 双重hash: 用一个64位hash拼出k个位置
+    fn indexes(&self, h: u64) -> impl Iterator<Item = usize> {
+        let h1 = h;
+        let h2 = h.rotate_left(32) | 1;
+        let m = self.num_bits as u64;
+        (0..self.num_hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % m) as usize)
+    }
+
+    // 格式(小端): "BLMF" | version u32 | num_bits u64 | num_hashes u32 | items u64 | bits
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + self.bits.len() * 8);
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        buf.extend_from_slice(&(self.num_bits as u64).to_le_bytes());
+        buf.extend_from_slice(&self.num_hashes.to_le_bytes());
+        buf.extend_from_slice(&(self.items as u64).to_le_bytes());
+        for w in &self.bits {
+            buf.extend_from_slice(&w.to_le_bytes());
+        }
+        buf
+    }
+
+    // magic不对、版本不认识或者长度对不上都返回None，调用方重建filter
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.get(0..4)? != MAGIC {
+            return None;
+        }
+        if u32::from_le_bytes(buf.get(4..8)?.try_into().ok()?) != VERSION {
+            return None;
+        }
+        let num_bits = u64::from_le_bytes(buf.get(8..16)?.try_into().ok()?) as usize;
+        let num_hashes = u32::from_le_bytes(buf.get(16..20)?.try_into().ok()?);
+        let items = u64::from_le_bytes(buf.get(20..HEADER_LEN)?.try_into().ok()?) as usize;
+        let words = buf.get(HEADER_LEN..)?;
+        if num_bits == 0
+            || !(1..=MAX_HASHES).contains(&num_hashes)
+            || words.len() != num_bits.div_ceil(64) * 8
+        {
+            return None;
+        }
+        let bits = words
+            .chunks_exact(8)
+            .map(|w| u64::from_le_bytes(w.try_into().unwrap()))
+            .collect();
+        Some(Self {
+            bits,
+            num_bits,
+            num_hashes,
+            items,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_test() {
+        let mut f = BloomFilter::new(1000, 0.01);
+        assert!(f.is_empty());
+        for i in 0..1000 {
+            f.insert(&i);
+        }
+        assert_eq!(f.len(), 1000);
+        for i in 0..1000 {
+            assert!(f.may_contain(&i));
+        }
+        let fp = (1000..11000).filter(|i| f.may_contain(i)).count();
+        assert!(fp < 300, "false positives: {}", fp);
+
+        f.clear();
+        assert!(!f.may_contain(&1));
+    }
+
+    #[test]
+    fn bytes_test() {
+        let mut f = BloomFilter::new(100, 0.05);
+        for s in ["a", "b", "c"] {
+            f.insert(s);
+        }
+        let g = BloomFilter::from_bytes(&f.to_bytes()).unwrap();
+        assert_eq!(f, g);
+        assert!(g.may_contain("b"));
+
+        assert_eq!(BloomFilter::from_bytes(&[1, 2, 3]), None);
+        assert_eq!(BloomFilter::from_bytes(&f.to_bytes()[..30]), None);
+
+        // hash次数是0或者大得离谱的也不读
+        for k in [0, MAX_HASHES + 1, u32::MAX] {
+            let mut bytes = f.to_bytes();
+            bytes[16..20].copy_from_slice(&k.to_le_bytes());
+            assert_eq!(BloomFilter::from_bytes(&bytes), None);
+        }
+
+        // 不认识的版本和magic都不读
+        let mut bytes = f.to_bytes();
+        bytes[4] = 2;
+        assert_eq!(BloomFilter::from_bytes(&bytes), None);
+        let mut bytes = f.to_bytes();
+        bytes[0] = b'X';
+        assert_eq!(BloomFilter::from_bytes(&bytes), None);
+    }
+
+    // hash值会落盘，钉死几个值，改了算法这里一定要挂
+    #[test]
+    fn stable_hash_test() {
+        let mut h = FnvHasher::new();
+        h.write(b"a");
+        assert_eq!(h.finish(), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(&1u32), hash(&1i32));
+        assert_eq!(hash(&7usize), hash(&7u64));
+        assert_eq!(hash(&42u64), 0xff3a_dd6b_3789_daef);
+        assert_eq!(hash("bloom"), 0xfe4e_a29b_5973_e6b9);
+    }
+}
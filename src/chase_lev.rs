@@ -0,0 +1,463 @@
+// Chase-Lev 工作窃取双端队列
+// Worker独占back一端，push/pop像栈一样(LIFO); Stealer可以有很多个，从front一端偷(FIFO)
+// 环形数组满了由Worker换一个两倍大的，旧数组可能还有Stealer在读，交给crossbeam epoch延迟释放
+
+use crossbeam::epoch::{self, Atomic, Owned};
+use std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr,
+    sync::{
+        atomic::{self, AtomicIsize, Ordering},
+        Arc,
+    },
+};
+
+const MIN_CAP: usize = 16;
+const MAX_BATCH: usize = 32;
+
+// 只管内存，不管里面的元素; 元素由Inner按[front, back)负责drop
+// 槽位要包一层UnsafeCell，不然通过&Buffer往里写是UB
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn new(cap: usize) -> Self {
+        debug_assert!(cap.is_power_of_two());
+        Self {
+            slots: (0..cap)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        }
+    }
+
+    fn cap(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn at(&self, i: isize) -> *mut MaybeUninit<T> {
+        let idx = i as usize & (self.cap() - 1);
+        self.slots[idx].get()
+    }
+
+    // 只是按位拷出来，可能和Worker的写并发，拿到以后要CAS成功才算数
+    unsafe fn read(&self, i: isize) -> MaybeUninit<T> {
+        ptr::read_volatile(self.at(i))
+    }
+
+    unsafe fn write(&self, i: isize, value: T) {
+        ptr::write_volatile(self.at(i), MaybeUninit::new(value))
+    }
+}
+
+struct Inner<T> {
+    front: AtomicIsize,
+    back: AtomicIsize,
+    buffer: Atomic<Buffer<T>>,
+}
+
+// 元素会在线程之间转移，所以只要求T: Send
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            let buf = self.buffer.load(Ordering::Relaxed, guard);
+            let (f, b) = (*self.front.get_mut(), *self.back.get_mut());
+            for i in f..b {
+                buf.deref().read(i).assume_init_drop();
+            }
+            drop(buf.into_owned());
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    Empty,
+    Success(T),
+    Retry, // 和别的线程抢输了，队列里可能还有
+}
+
+impl<T> Steal<T> {
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Steal::Empty)
+    }
+
+    pub fn is_retry(&self) -> bool {
+        matches!(self, Steal::Retry)
+    }
+
+    pub fn success(self) -> Option<T> {
+        match self {
+            Steal::Success(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    _marker: PhantomData<*mut ()>, // 只能有一个线程当owner: 可以Send，不能Sync
+}
+
+unsafe impl<T: Send> Send for Worker<T> {}
+
+impl<T> Worker<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                front: AtomicIsize::new(0),
+                back: AtomicIsize::new(0),
+                buffer: Atomic::new(Buffer::new(MIN_CAP)),
+            }),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let b = self.inner.back.load(Ordering::Relaxed);
+        let f = self.inner.front.load(Ordering::SeqCst);
+        (b - f).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 只有owner会换buffer，所以owner自己读buffer不用pin
+    fn buffer(&self) -> &Buffer<T> {
+        unsafe {
+            self.inner
+                .buffer
+                .load(Ordering::Relaxed, epoch::unprotected())
+                .deref()
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let b = self.inner.back.load(Ordering::Relaxed);
+        let f = self.inner.front.load(Ordering::Acquire);
+        if b - f >= self.buffer().cap() as isize {
+            self.resize(self.buffer().cap() * 2);
+        }
+        unsafe { self.buffer().write(b, value) };
+        // 先写元素再挪back，Stealer看到新的back时一定能读到元素
+        atomic::fence(Ordering::Release);
+        self.inner.back.store(b + 1, Ordering::Relaxed);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let b = self.inner.back.load(Ordering::Relaxed) - 1;
+        self.inner.back.store(b, Ordering::Relaxed);
+        // back先退一格再读front，和steal里先读front再读back配对
+        atomic::fence(Ordering::SeqCst);
+        let f = self.inner.front.load(Ordering::Relaxed);
+
+        if f > b {
+            self.inner.back.store(b + 1, Ordering::Relaxed);
+            return None;
+        }
+        let value = unsafe { self.buffer().read(b) };
+        if f == b {
+            // 只剩最后一个，和Stealer抢front
+            let won = self
+                .inner
+                .front
+                .compare_exchange(f, f + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            self.inner.back.store(b + 1, Ordering::Relaxed);
+            if !won {
+                return None;
+            }
+        } else {
+            let cap = self.buffer().cap();
+            if cap > MIN_CAP && ((b - f) as usize) < cap / 4 {
+                self.resize(cap / 2);
+            }
+        }
+        Some(unsafe { value.assume_init() })
+    }
+
+    // 把[front, back)按位搬到新数组，换上去以后旧数组等没人读了再释放
+    fn resize(&self, cap: usize) {
+        let b = self.inner.back.load(Ordering::Relaxed);
+        let f = self.inner.front.load(Ordering::Relaxed);
+        let old = self.buffer();
+        let new = Buffer::new(cap);
+        for i in f..b {
+            unsafe { ptr::copy_nonoverlapping(old.at(i), new.at(i), 1) };
+        }
+        let guard = &epoch::pin();
+        let old = self
+            .inner
+            .buffer
+            .swap(Owned::new(new), Ordering::Release, guard);
+        unsafe { guard.defer_destroy(old) };
+        if cap > 1024 {
+            guard.flush();
+        }
+    }
+}
+
+impl<T> Default for Worker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Stealer<T> {
+    pub fn len(&self) -> usize {
+        let f = self.inner.front.load(Ordering::Acquire);
+        atomic::fence(Ordering::SeqCst);
+        let b = self.inner.back.load(Ordering::Acquire);
+        (b - f).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn steal(&self) -> Steal<T> {
+        let f = self.inner.front.load(Ordering::Acquire);
+        atomic::fence(Ordering::SeqCst);
+        let guard = &epoch::pin();
+        let b = self.inner.back.load(Ordering::Acquire);
+        if b - f <= 0 {
+            return Steal::Empty;
+        }
+
+        let buf = self.inner.buffer.load(Ordering::Acquire, guard);
+        let value = unsafe { buf.deref().read(f) };
+        if self
+            .inner
+            .front
+            .compare_exchange(f, f + 1, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            // 读到的那份已经归别人了，不能drop
+            return Steal::Retry;
+        }
+        Steal::Success(unsafe { value.assume_init() })
+    }
+
+    // 一次偷走大约一半(最多MAX_BATCH个)放进dest，返回偷了几个
+    // 每个元素单独CAS front: 一次挪n格的话，Worker pop时只和front比较最后一个，
+    // 会把已经被这里读走的元素再pop一遍
+    pub fn steal_batch(&self, dest: &Worker<T>) -> Steal<usize> {
+        let n = match self.len() {
+            0 => return Steal::Empty,
+            len => len.div_ceil(2).min(MAX_BATCH),
+        };
+        let mut stolen = 0;
+        while stolen < n {
+            match self.steal() {
+                Steal::Success(v) => {
+                    dest.push(v);
+                    stolen += 1;
+                }
+                Steal::Retry if stolen == 0 => return Steal::Retry,
+                _ => break,
+            }
+        }
+        if stolen == 0 {
+            Steal::Empty
+        } else {
+            Steal::Success(stolen)
+        }
+    }
+}
+
+// crossbeam-epoch自己的链表过不了stacked borrows，miri要用tree borrows跑:
+// MIRIFLAGS="-Zmiri-tree-borrows" cargo +nightly miri test --lib chase_lev
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize},
+        thread,
+    };
+
+    #[test]
+    fn basics() {
+        let w = Worker::new();
+        let s = w.stealer();
+        assert_eq!(w.pop(), None);
+        assert!(s.steal().is_empty());
+
+        for i in 0..100 {
+            w.push(i);
+        }
+        assert_eq!(w.len(), 100);
+        assert_eq!(s.len(), 100);
+        assert_eq!(w.pop(), Some(99));
+        assert_eq!(s.steal(), Steal::Success(0));
+        assert_eq!(s.steal(), Steal::Success(1));
+        assert_eq!(w.pop(), Some(98));
+
+        let w2 = Worker::new();
+        assert_eq!(s.steal_batch(&w2), Steal::Success(32));
+        assert_eq!(w2.stealer().steal(), Steal::Success(2));
+        assert_eq!(w2.pop(), Some(33));
+        assert_eq!(s.steal(), Steal::Success(34));
+
+        while w.pop().is_some() {}
+        assert!(w.is_empty());
+        assert!(s.steal_batch(&w2).is_empty());
+        // 缩回去以后还能接着用
+        w.push(7);
+        assert_eq!(s.steal().success(), Some(7));
+    }
+
+    #[test]
+    fn drop_test() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        struct D(Arc<AtomicUsize>);
+        impl Drop for D {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let w = Worker::new();
+        let s = w.stealer();
+        for _ in 0..50 {
+            w.push(D(drops.clone()));
+        }
+        drop(w.pop());
+        drop(s.steal());
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        drop(w);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        drop(s);
+        assert_eq!(drops.load(Ordering::Relaxed), 50);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn concurrent_test() {
+        const N: usize = 100_000;
+        const THIEVES: usize = 4;
+        let w = Worker::new();
+        let done = Arc::new(AtomicBool::new(false));
+        let seen: Arc<Vec<AtomicUsize>> = Arc::new((0..N).map(|_| AtomicUsize::new(0)).collect());
+
+        let thieves: Vec<_> = (0..THIEVES)
+            .map(|t| {
+                let s = w.stealer();
+                let done = done.clone();
+                let seen = seen.clone();
+                thread::spawn(move || {
+                    let local = Worker::new();
+                    loop {
+                        let r = if t % 2 == 0 {
+                            s.steal()
+                        } else {
+                            s.steal_batch(&local).map_batch(&local)
+                        };
+                        match r {
+                            Steal::Success(i) => {
+                                seen[i].fetch_add(1, Ordering::Relaxed);
+                            }
+                            Steal::Empty if done.load(Ordering::Acquire) => break,
+                            _ => {}
+                        }
+                        while let Some(i) = local.pop() {
+                            seen[i].fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for i in 0..N {
+            w.push(i);
+            if i % 3 == 0 {
+                if let Some(j) = w.pop() {
+                    seen[j].fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        while let Some(j) = w.pop() {
+            seen[j].fetch_add(1, Ordering::Relaxed);
+        }
+        done.store(true, Ordering::Release);
+        for t in thieves {
+            t.join().unwrap();
+        }
+        assert!(seen.iter().all(|c| c.load(Ordering::Relaxed) == 1));
+    }
+
+    // Worker一边push一边pop到空，和批量偷的抢最后几个元素
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn batch_race_test() {
+        const N: usize = 200_000;
+        let w = Worker::new();
+        let s = w.stealer();
+        let done = Arc::new(AtomicBool::new(false));
+        let seen: Arc<Vec<AtomicUsize>> = Arc::new((0..N).map(|_| AtomicUsize::new(0)).collect());
+
+        let thief = {
+            let done = done.clone();
+            let seen = seen.clone();
+            thread::spawn(move || {
+                let local = Worker::<usize>::new();
+                while !done.load(Ordering::Acquire) || !s.is_empty() {
+                    s.steal_batch(&local);
+                    while let Some(i) = local.pop() {
+                        seen[i].fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+        };
+
+        for i in 0..N {
+            w.push(i);
+            if i % 4 == 3 {
+                while let Some(j) = w.pop() {
+                    seen[j].fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        while let Some(j) = w.pop() {
+            seen[j].fetch_add(1, Ordering::Relaxed);
+        }
+        done.store(true, Ordering::Release);
+        thief.join().unwrap();
+        assert!(seen.iter().all(|c| c.load(Ordering::Relaxed) == 1));
+    }
+
+    impl Steal<usize> {
+        // 测试里把批量偷的结果当作偷到了local里的一个
+        fn map_batch(self, local: &Worker<usize>) -> Steal<usize> {
+            match self {
+                Steal::Success(_) => local.pop().map_or(Steal::Retry, Steal::Success),
+                Steal::Empty => Steal::Empty,
+                Steal::Retry => Steal::Retry,
+            }
+        }
+    }
+}
@@ -262,6 +262,7 @@ mod tests {
     use std::{sync::Arc, thread};
 
     #[test]
+    #[cfg_attr(miri, ignore)]
     fn concurr() {
         let list = Arc::new(List::default());
         let mut threads = vec![];
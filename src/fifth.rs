@@ -0,0 +1,230 @@
+// 单链表实现的队列: 头出尾进，多存一个指向尾巴的裸指针，push_back就不用从头走到尾
+// head如果还用Box，每次移动Box都会让tail这个裸指针失效(stacked borrows)，
+// 所以干脆全部用裸指针，节点的所有权由链表自己管理
+
+use std::ptr;
+
+pub struct List<T> {
+    head: Link<T>,
+    tail: *mut Node<T>, // 空表时为null
+}
+
+type Link<T> = *mut Node<T>;
+
+struct Node<T> {
+    elem: T,
+    next: Link<T>,
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        Self {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        let new_tail = Box::into_raw(Box::new(Node {
+            elem,
+            next: ptr::null_mut(),
+        }));
+        if self.tail.is_null() {
+            self.head = new_tail;
+        } else {
+            unsafe { (*self.tail).next = new_tail };
+        }
+        self.tail = new_tail;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.head.is_null() {
+            return None;
+        }
+        // 从裸指针拿回Box，出了作用域就释放
+        let head = unsafe { Box::from_raw(self.head) };
+        self.head = head.next;
+        if self.head.is_null() {
+            self.tail = ptr::null_mut();
+        }
+        Some(head.elem)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        unsafe { self.head.as_ref().map(|node| &node.elem) }
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.head.as_mut().map(|node| &mut node.elem) }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(unsafe { self.head.as_ref() })
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut(unsafe { self.head.as_mut() })
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 一个个pop，不会像Box那样沿着next递归drop
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+// 裸指针默认不是Send/Sync，但节点只归这个链表所有
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Sync> Sync for List<T> {}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+pub struct Iter<'a, T>(Option<&'a Node<T>>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.map(|node| {
+            self.0 = unsafe { node.next.as_ref() };
+            &node.elem
+        })
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct IterMut<'a, T>(Option<&'a mut Node<T>>);
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.take().map(|node| {
+            self.0 = unsafe { node.next.as_mut() };
+            &mut node.elem
+        })
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basics() {
+        let mut list = List::new();
+        assert_eq!(list.pop_front(), None);
+        assert!(list.is_empty());
+
+        list.push_back(1);
+        list.push_back(2);
+        list.push_back(3);
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_front(), Some(2));
+
+        list.push_back(4);
+        list.push_back(5);
+        assert_eq!(list.pop_front(), Some(3));
+        assert_eq!(list.pop_front(), Some(4));
+        assert_eq!(list.pop_front(), Some(5));
+        assert_eq!(list.pop_front(), None);
+
+        // 清空以后tail也要重置
+        list.push_back(6);
+        list.push_back(7);
+        assert_eq!(list.pop_front(), Some(6));
+        assert_eq!(list.pop_front(), Some(7));
+        assert_eq!(list.pop_front(), None);
+    }
+
+    #[test]
+    fn peek_test() {
+        let mut list = List::new();
+        assert_eq!(list.peek(), None);
+        assert_eq!(list.peek_mut(), None);
+
+        list.push_back(1);
+        list.push_back(2);
+        assert_eq!(list.peek(), Some(&1));
+        if let Some(x) = list.peek_mut() {
+            *x = 10;
+        }
+        // peek_mut之后接着push，tail不能失效
+        list.push_back(3);
+        assert_eq!(list.pop_front(), Some(10));
+        assert_eq!(list.peek(), Some(&2));
+    }
+
+    #[test]
+    fn iter_test() {
+        let mut list = List::new();
+        for i in 1..=3 {
+            list.push_back(i);
+        }
+
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next(), None);
+
+        for x in &mut list {
+            *x *= 10;
+        }
+        list.push_back(40);
+        assert_eq!((&list).into_iter().sum::<i32>(), 100);
+
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next(), Some(10));
+        assert_eq!(iter.next(), Some(20));
+        assert_eq!(iter.collect::<Vec<_>>(), vec![30, 40]);
+    }
+
+    #[test]
+    fn long_list_test() {
+        let mut l = List::new();
+        for i in 0..100000 {
+            l.push_back(i);
+        }
+        drop(l)
+    }
+}
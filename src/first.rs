@@ -1,4 +1,10 @@
-use std::mem;
+use std::{
+    cmp::Ordering,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    mem,
+    panic::{self, AssertUnwindSafe},
+};
 
 pub struct List<T> {
     head: Link<T>, // 专门有一个pub的对外数据结构, 否则rust要求pub enum的成员必须全部是pub
@@ -41,14 +47,180 @@ impl<T> List<T> {
     pub fn peek_mut(&mut self) -> Option<&mut T> {
         self.head.as_mut().map(|node| &mut node.elem)
     }
+
+    pub fn iter(&self) -> ListIter<'_, T> {
+        ListIter(self.head.as_deref())
+    }
+
+    pub fn iter_mut(&mut self) -> ListMutIter<'_, T> {
+        ListMutIter(self.head.as_deref_mut())
+    }
+
+    // 从头前面的幽灵位置开始，只能往后走
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            current: None,
+            next: Some(&mut self.head),
+            index: None,
+        }
+    }
+
+    // 没有缓存长度，每次都数一遍
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn contains(&self, x: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.iter().any(|e| e == x)
+    }
+
+    pub fn clear(&mut self) {
+        // 交给Drop一个个拆，不会递归
+        drop(List {
+            head: self.head.take(),
+        });
+    }
+
+    // 下面这些都只是重新挂Box，元素本身不动
+
+    pub fn reverse(&mut self) {
+        let mut prev = None;
+        let mut cur = self.head.take();
+        while let Some(mut node) = cur {
+            cur = mem::replace(&mut node.nxt, prev);
+            prev = Some(node);
+        }
+        self.head = prev;
+    }
+
+    // 把other整个接到最后面(栈底)，other变成空表
+    pub fn append(&mut self, other: &mut Self) {
+        *tail_of(&mut self.head) = other.head.take();
+    }
+
+    // 切下[at, len)返回，self留下[0, at); at > len 时panic
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let mut link = &mut self.head;
+        for _ in 0..at {
+            link = &mut link.as_mut().expect("split_off index out of bounds").nxt;
+        }
+        List { head: link.take() }
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let mut link = &mut self.head;
+        while link.is_some() {
+            if f(&link.as_ref().unwrap().elem) {
+                link = &mut link.as_mut().unwrap().nxt;
+            } else {
+                let node = link.take().unwrap();
+                *link = node.nxt;
+            }
+        }
+    }
+}
+
+impl<T> List<T> {
+    pub fn sort(&mut self)
+    where
+        T: Ord,
+    {
+        self.sort_by(T::cmp)
+    }
+
+    pub fn sort_by_key<K: Ord, F: FnMut(&T) -> K>(&mut self, mut f: F) {
+        self.sort_by(|a, b| f(a).cmp(&f(b)))
+    }
+
+    // 自底向上的归并排序，和fourth::List::sort_by一样的思路，只是换成挪Box
+    // 每轮把相邻两段长度为width的链切下来合并到新链的尾巴上，width翻倍直到只剩一段
+    // 没有递归也不分配，相等时先取左边的，所以是稳定的
+    // cmp panic的时候所有节点都会接回self.head，顺序不保证但一个不丢
+    pub fn sort_by<F: FnMut(&T, &T) -> Ordering>(&mut self, mut cmp: F) {
+        let mut width = 1;
+        loop {
+            let mut rest = self.head.take();
+            let mut tail = &mut self.head;
+            let mut merges = 0;
+            while rest.is_some() {
+                let mut a = rest;
+                let mut b = cut(&mut a, width);
+                rest = cut(&mut b, width);
+                tail = merge_into(tail, a, b, &mut rest, &mut cmp);
+                merges += 1;
+            }
+            if merges <= 1 {
+                break;
+            }
+            width *= 2;
+        }
+    }
+}
+
+// 保留前n个节点，后面的切下来返回; 不够n个时返回None
+fn cut<T>(mut link: &mut Link<T>, n: usize) -> Link<T> {
+    for _ in 0..n {
+        link = &mut link.as_mut()?.nxt;
+    }
+    link.take()
+}
+
+// 把有序的a和b合并挂到tail上，返回合并后最后那根link
+// cmp panic时把剩下的a、b和rest原样接到tail后面再继续unwind，
+// 不然这几段散着的Box会在unwind时递归drop，长表直接爆栈
+fn merge_into<'a, T, F: FnMut(&T, &T) -> Ordering>(
+    mut tail: &'a mut Link<T>,
+    mut a: Link<T>,
+    mut b: Link<T>,
+    rest: &mut Link<T>,
+    cmp: &mut F,
+) -> &'a mut Link<T> {
+    loop {
+        let take_a = match (&a, &b) {
+            (Some(x), Some(y)) => {
+                match panic::catch_unwind(AssertUnwindSafe(|| cmp(&x.elem, &y.elem))) {
+                    Ok(ord) => ord != Ordering::Greater,
+                    Err(e) => {
+                        *tail = a;
+                        let t = tail_of(tail);
+                        *t = b;
+                        *tail_of(t) = rest.take();
+                        panic::resume_unwind(e);
+                    }
+                }
+            }
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => return tail,
+        };
+        let src = if take_a { &mut a } else { &mut b };
+        let mut node = src.take().unwrap();
+        *src = node.nxt.take();
+        tail = &mut tail.insert(node).nxt;
+    }
+}
+
+// 链表最后那根为None的link，空表时就是head本身
+fn tail_of<T>(mut link: &mut Link<T>) -> &mut Link<T> {
+    while link.is_some() {
+        link = &mut link.as_mut().unwrap().nxt;
+    }
+    link
 }
 
 impl<T> Drop for List<T> {
     fn drop(&mut self) {
-        let mut cur_link = mem::replace(&mut self.head, None); // 取出头的值
+        let mut cur_link = self.head.take(); // 取出头的值
         while let Some(mut boxed_node) = cur_link {
             // 只要头部还有值, 将头部的指针换掉，就不会发生deallocate的行为
-            cur_link = mem::replace(&mut boxed_node.nxt, None);
+            cur_link = boxed_node.nxt.take();
             // boxed_node现在是一个nil了，可以安全drop
         }
     }
@@ -90,10 +262,7 @@ impl<'a, T> IntoIterator for &'a List<T> {
     type IntoIter = ListIter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        match &self.head {
-            None => ListIter(None),
-            Some(node) => ListIter(Some(node.as_ref())),
-        }
+        self.iter()
     }
 }
 
@@ -116,13 +285,162 @@ impl<'a, T> IntoIterator for &'a mut List<T> {
     type IntoIter = ListMutIter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        match &mut self.head {
-            None => ListMutIter(None),
-            Some(node) => ListMutIter(Some(node.as_mut())),
+        self.iter_mut()
+    }
+}
+
+impl<T> Clone for ListIter<'_, T> {
+    fn clone(&self) -> Self {
+        ListIter(self.0)
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+// 和Vec一样，collect出来的顺序就是迭代的顺序: 第一个元素在栈顶
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut l = List::new();
+        l.extend(iter);
+        l
+    }
+}
+
+// 和collect保持一致，按顺序接到最后面(栈底)，不是一个个push
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut tail = tail_of(&mut self.head);
+        for elem in iter {
+            tail = &mut tail.insert(Box::new(Node { elem, nxt: None })).nxt;
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a> Extend<&'a T> for List<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+// derive出来的clone/eq会沿着nxt递归，长链表会爆栈，都改成用迭代器
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other)
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+// 先写长度，这样[[1], [2]]和[[1, 2]]的hash不一样
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for elem in self {
+            elem.hash(state);
         }
     }
 }
 
+// 单链表只能往后走，所以cursor只保存两样东西:
+// 当前元素，和当前元素后面那根link(在幽灵位置时就是head)
+// 把Node拆成elem和nxt两个不相交的可变借用，就不需要裸指针
+pub struct CursorMut<'a, T> {
+    current: Option<&'a mut T>,
+    next: Option<&'a mut Link<T>>, // 只在move_next里临时take出来，其他时候一定是Some
+    index: Option<usize>,
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        self.current.as_deref_mut()
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        self.link().as_deref_mut().map(|node| &mut node.elem)
+    }
+
+    // 已经在最后一个元素上时不动，返回false
+    pub fn move_next(&mut self) -> bool {
+        let link = self.next.take().unwrap();
+        if link.is_none() {
+            self.next = Some(link);
+            return false;
+        }
+        let Node { elem, nxt } = link.as_deref_mut().unwrap();
+        self.current = Some(elem);
+        self.next = Some(nxt);
+        self.index = Some(self.index.map_or(0, |i| i + 1));
+        true
+    }
+
+    // 插到当前元素后面，cursor不动; 在幽灵位置时插到最前面
+    pub fn insert_after(&mut self, elem: T) {
+        let link = self.link();
+        let nxt = link.take();
+        *link = Some(Box::new(Node { elem, nxt }));
+    }
+
+    // 删掉当前元素后面的那个
+    pub fn remove_after(&mut self) -> Option<T> {
+        let link = self.link();
+        link.take().map(|node| {
+            *link = node.nxt;
+            node.elem
+        })
+    }
+
+    // 切下当前元素之后的部分; 在幽灵位置时切下整个链表
+    pub fn split_after(&mut self) -> List<T> {
+        List {
+            head: self.link().take(),
+        }
+    }
+
+    // 把other整个接到当前元素后面，要先走到other的尾巴
+    pub fn splice_after(&mut self, mut other: List<T>) {
+        let tail = tail_of(&mut other.head);
+        let link = self.link();
+        *tail = link.take();
+        *link = other.head.take();
+    }
+
+    fn link(&mut self) -> &mut Link<T> {
+        self.next.as_deref_mut().unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -221,4 +539,278 @@ mod tests {
         assert_eq!(iter.next(), Some(&mut 2));
         assert_eq!(iter.next(), Some(&mut 1));
     }
+
+    fn collect<T: Clone>(l: &List<T>) -> Vec<T> {
+        l.iter().cloned().collect()
+    }
+
+    #[test]
+    fn traits_test() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash<T: Hash>(x: &T) -> u64 {
+            let mut h = DefaultHasher::new();
+            x.hash(&mut h);
+            h.finish()
+        }
+
+        let mut l: List<i32> = (1..=3).collect();
+        assert_eq!(l.peek(), Some(&1));
+        assert_eq!(format!("{:?}", l), "[1, 2, 3]");
+        l.extend([4, 5]);
+        l.extend(&[6]);
+        assert_eq!(collect(&l), vec![1, 2, 3, 4, 5, 6]);
+
+        let mut c = l.clone();
+        assert_eq!(c, l);
+        assert_eq!(hash(&c), hash(&l));
+        for x in c.iter_mut() {
+            *x *= 10;
+        }
+        assert_ne!(c, l);
+        assert!(l < c);
+        assert_eq!(c.iter().sum::<i32>(), 210);
+
+        let a: List<List<i32>> = [vec![1], vec![2]]
+            .into_iter()
+            .map(|v| v.into_iter().collect())
+            .collect();
+        let b: List<List<i32>> = [vec![1, 2]]
+            .into_iter()
+            .map(|v| v.into_iter().collect())
+            .collect();
+        assert_ne!(hash(&a), hash(&b));
+        assert_eq!(List::<i32>::default(), List::new());
+    }
+
+    #[test]
+    fn long_traits_test() {
+        let l: List<i32> = (0..100000).collect();
+        let c = l.clone();
+        assert!(c == l);
+        assert_eq!(c.cmp(&l), Ordering::Equal);
+    }
+
+    #[test]
+    fn cursor_test() {
+        let mut l = List::new();
+        for i in (1..=5).rev() {
+            l.push(i);
+        }
+
+        let mut c = l.cursor_mut();
+        assert_eq!(c.index(), None);
+        assert_eq!(c.current(), None);
+        assert_eq!(c.peek_next(), Some(&mut 1));
+        c.insert_after(0);
+        assert!(c.move_next());
+        assert_eq!(c.current(), Some(&mut 0));
+        assert!(c.move_next());
+        assert_eq!(c.index(), Some(1));
+        *c.current().unwrap() = 10;
+        assert_eq!(c.remove_after(), Some(2));
+        assert_eq!(c.peek_next(), Some(&mut 3));
+        assert_eq!(collect(&l), vec![0, 10, 3, 4, 5]);
+
+        // 边走边删: 去掉所有奇数
+        let mut c = l.cursor_mut();
+        loop {
+            match c.peek_next() {
+                Some(x) if *x % 2 == 1 => {
+                    c.remove_after();
+                }
+                Some(_) => {
+                    c.move_next();
+                }
+                None => break,
+            }
+        }
+        assert!(!c.move_next());
+        assert_eq!(c.remove_after(), None);
+        assert_eq!(collect(&l), vec![0, 10, 4]);
+    }
+
+    #[test]
+    fn cursor_split_splice_test() {
+        let mut l = List::new();
+        for i in (0..6).rev() {
+            l.push(i);
+        }
+
+        let mut c = l.cursor_mut();
+        c.move_next();
+        c.move_next();
+        let mut tail = c.split_after();
+        assert_eq!(collect(&l), vec![0, 1]);
+        assert_eq!(collect(&tail), vec![2, 3, 4, 5]);
+
+        let mut c = tail.cursor_mut();
+        c.move_next();
+        c.splice_after(l);
+        assert_eq!(collect(&tail), vec![2, 0, 1, 3, 4, 5]);
+
+        let mut c = tail.cursor_mut();
+        c.splice_after(List::new());
+        let all = c.split_after();
+        assert_eq!(tail.peek(), None);
+        assert_eq!(collect(&all), vec![2, 0, 1, 3, 4, 5]);
+
+        // 整个挂到一个空表里
+        let mut l = List::new();
+        l.cursor_mut().splice_after(all);
+        assert_eq!(collect(&l), vec![2, 0, 1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn structural_test() {
+        let mut l = List::new();
+        assert!(l.is_empty());
+        l.reverse();
+        for i in (0..5).rev() {
+            l.push(i);
+        }
+        assert_eq!(l.len(), 5);
+        assert!(l.contains(&3));
+        assert!(!l.contains(&5));
+
+        l.reverse();
+        assert_eq!(collect(&l), vec![4, 3, 2, 1, 0]);
+
+        let mut tail = l.split_off(2);
+        assert_eq!(collect(&l), vec![4, 3]);
+        assert_eq!(collect(&tail), vec![2, 1, 0]);
+        assert_eq!(l.split_off(2).len(), 0);
+
+        tail.append(&mut l);
+        assert!(l.is_empty());
+        assert_eq!(collect(&tail), vec![2, 1, 0, 4, 3]);
+        l.append(&mut tail);
+        assert_eq!(collect(&l), vec![2, 1, 0, 4, 3]);
+
+        l.retain(|x| x % 2 == 0);
+        assert_eq!(collect(&l), vec![2, 0, 4]);
+        l.retain(|_| false);
+        assert!(l.is_empty());
+
+        l.push(1);
+        l.clear();
+        assert_eq!(l.pop(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "split_off index out of bounds")]
+    fn split_off_out_of_bounds_test() {
+        let mut l = List::new();
+        l.push(1);
+        l.split_off(2);
+    }
+
+    #[test]
+    fn long_structural_test() {
+        let mut l = List::new();
+        for i in 0..100000 {
+            l.push(i);
+        }
+        l.reverse();
+        assert_eq!(l.peek(), Some(&0));
+        let mut tail = l.split_off(50000);
+        tail.append(&mut l);
+        assert_eq!(tail.peek(), Some(&50000));
+        tail.retain(|x| x % 3 == 0);
+        assert_eq!(tail.len(), 33334);
+        tail.clear();
+    }
+
+    #[test]
+    fn sort_panic_test() {
+        let mut l = List::new();
+        for i in 0..200_000 {
+            l.push(i % 1000);
+        }
+        let mut calls = 0;
+        let r = panic::catch_unwind(AssertUnwindSafe(|| {
+            l.sort_by(|a, b| {
+                calls += 1;
+                if calls == 5 {
+                    panic!("stop");
+                }
+                a.cmp(b)
+            })
+        }));
+        assert!(r.is_err());
+        assert_eq!(l.len(), 200_000);
+        assert_eq!(l.iter().map(|&x| x as u64).sum::<u64>(), 200 * 499_500);
+
+        // 后面几轮里panic也一样
+        let mut calls = 0;
+        let r = panic::catch_unwind(AssertUnwindSafe(|| {
+            l.sort_by(|a, b| {
+                calls += 1;
+                if calls == 1_000_000 {
+                    panic!("stop");
+                }
+                a.cmp(b)
+            })
+        }));
+        assert!(r.is_err());
+        assert_eq!(l.len(), 200_000);
+        l.sort();
+        assert_eq!(l.peek(), Some(&0));
+        drop(l);
+    }
+
+    #[test]
+    fn sort_test() {
+        let mut l = List::new();
+        l.sort();
+        l.push(1);
+        l.sort();
+        assert_eq!(collect(&l), vec![1]);
+
+        for x in [3, 1, 4, 1, 5, 9, 2, 6] {
+            l.push(x);
+        }
+        l.sort();
+        assert_eq!(collect(&l), vec![1, 1, 1, 2, 3, 4, 5, 6, 9]);
+        l.sort_by(|a, b| b.cmp(a));
+        assert_eq!(collect(&l), vec![9, 6, 5, 4, 3, 2, 1, 1, 1]);
+
+        // 稳定: 按key相等的保持原来的顺序
+        let mut l = List::new();
+        for p in [(1, 'a'), (0, 'b'), (1, 'c'), (0, 'd'), (1, 'e')]
+            .into_iter()
+            .rev()
+        {
+            l.push(p);
+        }
+        l.sort_by_key(|p| p.0);
+        assert_eq!(
+            collect(&l),
+            vec![(0, 'b'), (0, 'd'), (1, 'a'), (1, 'c'), (1, 'e')]
+        );
+
+        let mut rng = rand::thread_rng();
+        let mut v: Vec<i32> = (0..1000)
+            .map(|_| rand::Rng::gen_range(&mut rng, -100..100))
+            .collect();
+        let mut l = List::new();
+        for &x in v.iter().rev() {
+            l.push(x);
+        }
+        l.sort();
+        v.sort();
+        assert_eq!(collect(&l), v);
+    }
+
+    #[test]
+    fn long_sort_test() {
+        let mut l = List::new();
+        for i in 0..200000 {
+            l.push(i);
+        }
+        l.sort();
+        assert_eq!(l.peek(), Some(&0));
+        assert_eq!(l.len(), 200000);
+        assert!(collect(&l).windows(2).all(|w| w[0] < w[1]));
+    }
 }
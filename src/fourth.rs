@@ -1,7 +1,15 @@
 // unsafe实现 双端 队列链表
 
 use std::{
+    alloc::{handle_alloc_error, Allocator, Global, Layout},
+    cmp::Ordering,
     fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    iter::FusedIterator,
+    marker::PhantomData,
+    mem,
+    ops::{Bound, RangeBounds},
+    panic::{self, AssertUnwindSafe},
     ptr::NonNull,
 };
 
@@ -9,10 +17,16 @@ use std::{
 // 1. 在开始时，将输入参数中的引用转换成裸指针
 // 2. 在函数体中只使用裸指针
 // 3. 返回之前，将裸指针转换成安全的指针
-pub struct List<T: Debug> {
+pub struct List<T, A: Allocator = Global> {
     length: usize,
+    capacity: usize, // 不限容量时是usize::MAX
+    policy: OverflowPolicy,
     head: Link<T>,
     tail: Link<T>,
+    alloc: A, // 所有节点都从这里分配、还回这里
+    #[cfg(debug_assertions)]
+    id: usize,
+    _marker: PhantomData<Box<Node<T>, A>>, // 告诉编译器(drop check)这里拥有Node<T>，裸指针本身不表示所有权
 }
 
 // NonNull 为 Rust 中的一个内置类型，其是裸指针 *mut T 的一个包装
@@ -24,109 +38,119 @@ pub struct List<T: Debug> {
 type Link<T> = Option<NonNull<Node<T>>>; // 将空指针转成null，保证里面是nonnull的
 
 #[derive(Debug)]
-struct Node<T: Debug> {
+struct Node<T> {
     elem: T,
     next: Link<T>,
     prev: Link<T>,
+    #[cfg(debug_assertions)]
+    owner: usize, // 所在链表的id，debug下check_handle用
 }
 
-impl<T: Debug> Node<T> {
+// debug下每个链表一个id，0表示不在任何链表里
+#[cfg(debug_assertions)]
+static NEXT_LIST_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(1);
+
+// 有界链表满了以后push_front/push_back怎么办
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    Reject,          // 不放进去，try_push_*返回Err(elem)，push_*直接panic
+    EvictOpposite,   // 从另一头pop一个扔掉
+    OverwriteOldest, // 把另一头(最老的)节点挪过来覆盖，不重新分配
+}
+
+impl<T> Node<T> {
     pub fn new(elem: T) -> Node<T> {
         Node {
             elem,
             next: None,
             prev: None,
+            #[cfg(debug_assertions)]
+            owner: 0,
         }
     }
 
-    pub fn into_val(self: Box<Self>) -> T {
+    // 拿走整个Box，出作用域时用它自己的allocator释放节点
+    #[allow(clippy::boxed_local)]
+    pub fn into_val<A: Allocator>(self: Box<Self, A>) -> T {
         self.elem
     }
 }
 
-impl<T: Debug> List<T> {
+impl<T> List<T> {
     pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn bounded(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self::bounded_in(capacity, policy, Global)
+    }
+}
+
+impl<T, A: Allocator> List<T, A> {
+    pub fn new_in(alloc: A) -> Self {
         Self {
             length: 0,
+            capacity: usize::MAX,
+            policy: OverflowPolicy::Reject,
             head: None,
             tail: None,
+            alloc,
+            #[cfg(debug_assertions)]
+            id: NEXT_LIST_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            _marker: PhantomData,
         }
     }
 
-    pub fn push_front(&mut self, elem: T) {
-        let mut new_head = Box::new(Node::new(elem));
-
-        new_head.next = self.head;
-        new_head.prev = None;
-        let new_head = NonNull::new(Box::into_raw(new_head));
+    // 最多放capacity个元素，满了以后push_*按policy处理
+    // 其他插入(insert_by_index、append、splice、cursor、handle)超过容量时panic
+    pub fn bounded_in(capacity: usize, policy: OverflowPolicy, alloc: A) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        let mut l = Self::new_in(alloc);
+        l.capacity = capacity;
+        l.policy = policy;
+        l
+    }
 
-        match self.head {
-            None => {
-                self.tail = new_head;
-            }
-            Some(old_head) => unsafe {
-                (*old_head.as_ptr()).prev = new_head;
-            },
-        }
-        self.head = new_head;
-        self.length += 1;
+    pub fn allocator(&self) -> &A {
+        &self.alloc
     }
 
-    pub fn push_back(&mut self, elem: T) {
-        let mut new_tail = Box::new(Node::new(elem));
-        new_tail.prev = self.tail;
-        new_tail.next = None;
-        let new_tail = NonNull::new(Box::into_raw(new_tail));
-
-        match self.tail {
-            Some(old_tail) => unsafe {
-                (*old_tail.as_ptr()).next = new_tail;
-            },
-            None => {
-                self.head = new_tail;
-            }
-        }
+    // 不限容量时返回None
+    pub fn capacity(&self) -> Option<usize> {
+        (self.capacity != usize::MAX).then_some(self.capacity)
+    }
 
-        self.tail = new_tail;
-        self.length += 1;
+    pub fn is_full(&self) -> bool {
+        self.length >= self.capacity
     }
 
-    pub fn pop_front(&mut self) -> Option<T> {
-        self.head.map(|old_head| unsafe {
-            let old_head = Box::from_raw(old_head.as_ptr()); // 必须换成Box，否则还要自己处理后事
+    // 满了且策略是Reject时panic; 按策略挤掉的元素直接drop
+    pub fn push_front(&mut self, elem: T) {
+        assert!(self.push_with(elem, true, false).is_ok(), "list is full");
+    }
 
-            match old_head.next {
-                Some(new_head) => {
-                    (*new_head.as_ptr()).prev = None;
-                }
-                None => {
-                    self.tail = None;
-                }
-            }
+    pub fn push_back(&mut self, elem: T) {
+        assert!(self.push_with(elem, false, false).is_ok(), "list is full");
+    }
 
-            self.head = old_head.next;
-            self.length -= 1;
-            old_head.into_val()
-        })
+    // 满了(Reject)或者分配失败时把elem还回来，不会panic也不会abort
+    // 按策略挤掉了另一头的元素时把它返回，调用方可以回收或者记下来
+    pub fn try_push_front(&mut self, elem: T) -> Result<Option<T>, T> {
+        self.push_with(elem, true, true)
     }
 
-    pub fn pop_back(&mut self) -> Option<T> {
-        self.tail.map(|old_tail| unsafe {
-            let old_tail = Box::from_raw(old_tail.as_ptr());
+    pub fn try_push_back(&mut self, elem: T) -> Result<Option<T>, T> {
+        self.push_with(elem, false, true)
+    }
 
-            match old_tail.prev {
-                Some(new_tail) => {
-                    (*new_tail.as_ptr()).next = None;
-                }
-                None => {
-                    self.head = None;
-                }
-            }
+    pub fn pop_front(&mut self) -> Option<T> {
+        let head = self.head?;
+        unsafe { Some(self.unlink_node(head)) }
+    }
 
-            self.tail = old_tail.next;
-            self.length -= 1;
-            old_tail.into_val()
-        })
+    pub fn pop_back(&mut self) -> Option<T> {
+        let tail = self.tail?;
+        unsafe { Some(self.unlink_node(tail)) }
     }
 
     pub fn peek_front(&self) -> Option<&T> {
@@ -192,7 +216,8 @@ impl<T: Debug> List<T> {
     }
 
     // idx in range [0, len)
-    pub fn get_mut_by_idx(&self, idx: usize) -> Option<&mut T> {
+    // 必须是&mut self，否则可以从同一个&List拿到两个指向同一元素的&mut T
+    pub fn get_mut_by_idx(&mut self, idx: usize) -> Option<&mut T> {
         if idx >= self.length {
             return None;
         }
@@ -204,7 +229,7 @@ impl<T: Debug> List<T> {
         }
     }
 
-    fn get_mut_by_idx_from_tail(&self, idx: usize) -> Option<&mut T> {
+    fn get_mut_by_idx_from_tail(&mut self, idx: usize) -> Option<&mut T> {
         let mut cur_p = self.tail.as_ref();
         let mut cur_idx = 0;
         while let Some(node) = cur_p {
@@ -219,7 +244,7 @@ impl<T: Debug> List<T> {
         None
     }
 
-    fn get_mut_by_idx_from_head(&self, idx: usize) -> Option<&mut T> {
+    fn get_mut_by_idx_from_head(&mut self, idx: usize) -> Option<&mut T> {
         let mut cur_p = self.head.as_ref();
         let mut cur_idx = 0;
         while let Some(node) = cur_p {
@@ -249,132 +274,2123 @@ impl<T: Debug> List<T> {
             return;
         }
 
-        let mut new_node = Box::new(Node::new(elem)); // 放在堆上
+        // 去掉了插在两头的情况，所以前后一定都有节点
+        self.reserve(1);
+        let next = self.node_at(idx).unwrap();
+        let node = self.new_node(elem);
+        unsafe {
+            let prev = (*next.as_ptr()).prev;
+            self.link_nodes(prev, Some(next), node, node, 1);
+        }
+    }
+
+    // 切下[at, len)返回，self留下[0, at); 从离at近的一头走过去
+    // at > len 时panic
+    pub fn split_off(&mut self, at: usize) -> Self
+    where
+        A: Clone,
+    {
+        assert!(at <= self.length, "split_off index out of bounds");
+        match (self.node_at(at), self.tail) {
+            (Some(first), Some(last)) => unsafe {
+                self.unlink_nodes(first, last, self.length - at)
+            },
+            _ => List::new_in(self.alloc.clone()),
+        }
+    }
+}
 
-        let mut cur_p = self.head;
-        let mut cur_idx = 0;
-        while let Some(cur_node) = cur_p {
-            if cur_idx + 1 == idx {
-                new_node.prev = cur_p;
-                unsafe {
-                    new_node.next = (*cur_node.as_ptr()).next;
-                    let new_node_ptr = NonNull::new(Box::into_raw(new_node)); // !!!这里不能把into_raw写成&mut *new_node, 否则boxdrop后指针依然存在，会导致野指针
-                    if let Some(next_node) = (*cur_node.as_ptr()).next {
-                        // 因为去掉了插最后一个点的情况，所以这里一定是Some
-                        (*next_node.as_ptr()).prev = new_node_ptr;
+// 从别的链表挪节点过来的操作只给Global用: 挪过来的节点以后由self.alloc释放，
+// 另一个表的allocator就算类型一样也可能是别的实例(比如指向不同内存池的&A)
+impl<T> List<T> {
+    // 把other整个接到尾巴上，other变成空表; 只改两头的指针
+    pub fn append(&mut self, other: &mut Self) {
+        self.reserve(other.length);
+        if let Some((first, last, count)) = other.take_nodes() {
+            unsafe { self.link_nodes(self.tail, None, first, last, count) }
+        }
+    }
+
+    // 把other插到idx的位置，插完后other的第一个元素下标是idx
+    // idx > len 时panic
+    pub fn splice(&mut self, idx: usize, mut other: Self) {
+        assert!(idx <= self.length, "splice index out of bounds");
+        self.reserve(other.length);
+        let Some((first, last, count)) = other.take_nodes() else {
+            return;
+        };
+        let next = self.node_at(idx);
+        let prev = match next {
+            Some(next) => unsafe { (*next.as_ptr()).prev },
+            None => self.tail,
+        };
+        unsafe { self.link_nodes(prev, next, first, last, count) }
+    }
+
+    // 两个有序表线性合并到self里，other变成空表; 相等时self的元素在前
+    pub fn merge(&mut self, other: &mut Self)
+    where
+        T: Ord,
+    {
+        self.merge_by(other, T::cmp)
+    }
+
+    pub fn merge_by<F: FnMut(&T, &T) -> Ordering>(&mut self, other: &mut Self, mut cmp: F) {
+        self.reserve(other.length);
+        let mut cur = self.head;
+        unsafe {
+            while let Some(node) = other.head {
+                while let Some(c) = cur {
+                    if cmp(&(*c.as_ptr()).elem, &(*node.as_ptr()).elem) == Ordering::Greater {
+                        break;
                     }
-                    (*cur_node.as_ptr()).next = new_node_ptr;
+                    cur = (*c.as_ptr()).next;
+                }
+                if cur.is_none() {
+                    // 剩下的都比self大，整段接到后面
+                    self.append(other);
+                    return;
                 }
-                self.length += 1;
-                break;
+                other.detach_node(node);
+                let prev = cur.and_then(|c| (*c.as_ptr()).prev);
+                self.link_nodes(prev, cur, node, node, 1);
             }
-            unsafe { cur_p = (*cur_node.as_ptr()).next }
-            cur_idx += 1;
         }
     }
 }
 
-impl<T: Debug> Default for List<T> {
-    fn default() -> Self {
-        Self::new()
+// RangeBounds换成[start, end)，越界时panic; rope等按下标取区间的地方共用
+// 和std的切片一样，..=usize::MAX这种区间直接报溢出，不会绕回去
+pub(crate) fn range_bounds<R: RangeBounds<usize>>(range: &R, len: usize) -> (usize, usize) {
+    let start = match range.start_bound() {
+        Bound::Included(&n) => n,
+        Bound::Excluded(&n) => n.checked_add(1).expect("range start overflow"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&n) => n.checked_add(1).expect("range end overflow"),
+        Bound::Excluded(&n) => n,
+        Bound::Unbounded => len,
+    };
+    assert!(start <= end && end <= len, "range out of bounds");
+    (start, end)
+}
+
+// 一串连好的节点: (第一个, 最后一个, 个数)
+type Chain<T> = (NonNull<Node<T>>, NonNull<Node<T>>, usize);
+
+// 链表内部的裸指针操作，cursor和其他结构性操作都建立在这几个函数上
+// 分配和释放只在new_node/try_new_node/free_node里，其他地方只搬指针
+impl<T, A: Allocator> List<T, A> {
+    fn new_node(&self, elem: T) -> NonNull<Node<T>> {
+        match self.try_new_node(elem) {
+            Ok(node) => node,
+            Err(_) => handle_alloc_error(Layout::new::<Node<T>>()),
+        }
+    }
+
+    // 满了以后按策略腾位置，fallible为false时分配失败直接abort
+    // 先分配再evict，分配失败不会白丢一个元素
+    fn push_with(&mut self, elem: T, front: bool, fallible: bool) -> Result<Option<T>, T> {
+        let mut old = None;
+        let node = if !self.is_full() {
+            self.alloc_node(elem, fallible)?
+        } else {
+            match self.policy {
+                OverflowPolicy::Reject => return Err(elem),
+                OverflowPolicy::EvictOpposite => {
+                    let node = self.alloc_node(elem, fallible)?;
+                    old = if front {
+                        self.pop_back()
+                    } else {
+                        self.pop_front()
+                    };
+                    node
+                }
+                OverflowPolicy::OverwriteOldest => unsafe {
+                    let node = if front { self.tail } else { self.head }.unwrap();
+                    self.detach_node(node);
+                    old = Some(mem::replace(&mut (*node.as_ptr()).elem, elem));
+                    node
+                },
+            }
+        };
+        unsafe {
+            if front {
+                self.link_nodes(None, self.head, node, node, 1)
+            } else {
+                self.link_nodes(self.tail, None, node, node, 1)
+            }
+        }
+        // 旧元素等链表恢复完整了才交出去，调用方drop时panic也不会留下坏掉的链表
+        Ok(old)
+    }
+
+    fn alloc_node(&self, elem: T, fallible: bool) -> Result<NonNull<Node<T>>, T> {
+        if fallible {
+            self.try_new_node(elem)
+        } else {
+            Ok(self.new_node(elem))
+        }
+    }
+
+    // 不走policy的插入用这个检查容量
+    fn reserve(&self, count: usize) {
+        assert!(count <= self.capacity - self.length, "list is full");
+    }
+
+    fn try_new_node(&self, elem: T) -> Result<NonNull<Node<T>>, T> {
+        match self.alloc.allocate(Layout::new::<Node<T>>()) {
+            Ok(ptr) => {
+                let node = ptr.cast::<Node<T>>();
+                unsafe { node.as_ptr().write(Node::new(elem)) };
+                Ok(node)
+            }
+            Err(_) => Err(elem),
+        }
+    }
+
+    // node必须是从self.alloc分配的，并且已经不在任何链表上
+    unsafe fn free_node(&self, node: NonNull<Node<T>>) -> T {
+        Box::from_raw_in(node.as_ptr(), &self.alloc).into_val()
+    }
+
+    // 把first..=last这一串(count个)节点接到prev和next之间，要求prev.next == next
+    // prev为None表示接在最前面，next为None表示接在最后面
+    unsafe fn link_nodes(
+        &mut self,
+        prev: Link<T>,
+        next: Link<T>,
+        first: NonNull<Node<T>>,
+        last: NonNull<Node<T>>,
+        count: usize,
+    ) {
+        // 整串从别的链表(或者刚分配出来)接进来时改一遍owner，只有debug下才有这一步
+        #[cfg(debug_assertions)]
+        if (*first.as_ptr()).owner != self.id {
+            let mut cur = first;
+            loop {
+                (*cur.as_ptr()).owner = self.id;
+                if cur == last {
+                    break;
+                }
+                cur = (*cur.as_ptr()).next.unwrap();
+            }
+        }
+        (*first.as_ptr()).prev = prev;
+        (*last.as_ptr()).next = next;
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = Some(first),
+            None => self.head = Some(first),
+        }
+        match next {
+            Some(next) => (*next.as_ptr()).prev = Some(last),
+            None => self.tail = Some(last),
+        }
+        self.length += count;
+    }
+
+    // 把first..=last这一串(count个)节点摘下来，变成一个新链表
+    unsafe fn unlink_nodes(
+        &mut self,
+        first: NonNull<Node<T>>,
+        last: NonNull<Node<T>>,
+        count: usize,
+    ) -> Self
+    where
+        A: Clone,
+    {
+        self.detach_nodes(first, last, count);
+        let mut l = List::new_in(self.alloc.clone());
+        l.link_nodes(None, None, first, last, count);
+        l
+    }
+
+    // 只把first..=last摘下来，不管它们之后去哪
+    unsafe fn detach_nodes(
+        &mut self,
+        first: NonNull<Node<T>>,
+        last: NonNull<Node<T>>,
+        count: usize,
+    ) {
+        let prev = (*first.as_ptr()).prev;
+        let next = (*last.as_ptr()).next;
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => (*next.as_ptr()).prev = prev,
+            None => self.tail = prev,
+        }
+        (*first.as_ptr()).prev = None;
+        (*last.as_ptr()).next = None;
+        self.length -= count;
+    }
+
+    // 摘下单个节点并释放，拿回里面的元素
+    unsafe fn unlink_node(&mut self, node: NonNull<Node<T>>) -> T {
+        self.detach_node(node);
+        self.free_node(node)
+    }
+
+    // 只摘下来不释放，节点之后还要接回某个链表
+    unsafe fn detach_node(&mut self, node: NonNull<Node<T>>) {
+        self.detach_nodes(node, node, 1)
+    }
+
+    // 下标为idx的节点，从离得近的一头找; idx >= len 时返回None
+    fn node_at(&self, idx: usize) -> Link<T> {
+        if idx >= self.length {
+            return None;
+        }
+        if idx > self.length >> 1 {
+            let mut cur = self.tail;
+            for _ in idx + 1..self.length {
+                cur = cur.and_then(|node| unsafe { (*node.as_ptr()).prev });
+            }
+            cur
+        } else {
+            let mut cur = self.head;
+            for _ in 0..idx {
+                cur = cur.and_then(|node| unsafe { (*node.as_ptr()).next });
+            }
+            cur
+        }
+    }
+
+    // 交出所有节点，自己变成空表
+    fn take_nodes(&mut self) -> Option<Chain<T>> {
+        let len = mem::replace(&mut self.length, 0);
+        match (self.head.take(), self.tail.take()) {
+            (Some(head), Some(tail)) => Some((head, tail, len)),
+            _ => None,
+        }
     }
 }
 
-impl<T: Debug> Display for List<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut cur_p = self.head;
-        let mut dis_str = String::from("");
-        while let Some(cur_node) = cur_p {
-            unsafe {
-                cur_p = (*cur_node.as_ptr()).next;
-                dis_str += format!("{:?}=>", (*cur_node.as_ptr()).elem).as_str();
+// 排序和有序表操作，都只改指针不搬元素，也不额外分配
+impl<T, A: Allocator> List<T, A> {
+    pub fn sort(&mut self)
+    where
+        T: Ord,
+    {
+        self.sort_by(T::cmp)
+    }
+
+    pub fn sort_by_key<K: Ord, F: FnMut(&T) -> K>(&mut self, mut f: F) {
+        self.sort_by(|a, b| f(a).cmp(&f(b)))
+    }
+
+    // 自底向上的归并排序: 每轮把长度为width的相邻两段合并，width翻倍直到只剩一段
+    // 合并时只顺着next接，全部排完再一遍补上prev; 相等时先取左边的，所以是稳定的
+    // cmp panic时先把链表接回完整的一条再继续unwind，元素顺序不保证但不会丢、不会坏
+    pub fn sort_by<F: FnMut(&T, &T) -> Ordering>(&mut self, mut cmp: F) {
+        if self.length < 2 {
+            return;
+        }
+        unsafe {
+            let mut head = self.head;
+            let mut width = 1;
+            loop {
+                let mut p = head;
+                let mut tail: Link<T> = None;
+                let mut merges = 0;
+                head = None;
+
+                while p.is_some() {
+                    merges += 1;
+                    let mut q = p;
+                    let mut psize = 0;
+                    while psize < width {
+                        psize += 1;
+                        q = q.and_then(|node| (*node.as_ptr()).next);
+                        if q.is_none() {
+                            break;
+                        }
+                    }
+                    let mut qsize = width;
+
+                    while psize > 0 || (qsize > 0 && q.is_some()) {
+                        let take_p = match q {
+                            _ if psize == 0 => false,
+                            Some(qn) if qsize > 0 => {
+                                let pn = p.unwrap();
+                                let ord = panic::catch_unwind(AssertUnwindSafe(|| {
+                                    cmp(&(*pn.as_ptr()).elem, &(*qn.as_ptr()).elem)
+                                }));
+                                match ord {
+                                    Ok(ord) => ord != Ordering::Greater,
+                                    Err(e) => {
+                                        self.relink_sorting(head, tail, p, psize, q);
+                                        panic::resume_unwind(e)
+                                    }
+                                }
+                            }
+                            _ => true,
+                        };
+                        let e = if take_p {
+                            let e = p;
+                            p = p.and_then(|node| (*node.as_ptr()).next);
+                            psize -= 1;
+                            e
+                        } else {
+                            let e = q;
+                            q = q.and_then(|node| (*node.as_ptr()).next);
+                            qsize -= 1;
+                            e
+                        };
+                        match tail {
+                            Some(t) => (*t.as_ptr()).next = e,
+                            None => head = e,
+                        }
+                        tail = e;
+                    }
+                    p = q;
+                }
+                if let Some(t) = tail {
+                    (*t.as_ptr()).next = None;
+                }
+                if merges <= 1 {
+                    break;
+                }
+                width *= 2;
             }
+
+            self.relink_prev(head);
         }
+    }
 
-        let mut cur_p = self.tail;
-        while let Some(cur_node) = cur_p {
-            unsafe {
-                cur_p = (*cur_node.as_ptr()).prev;
-                dis_str += format!("{:?}<=", (*cur_node.as_ptr()).elem).as_str();
+    // 排序中途的节点分三段: 已经合并好的head..=tail，p开始还剩psize个，q开始一直到结尾
+    // 把它们按这个顺序接回一条链
+    unsafe fn relink_sorting(
+        &mut self,
+        mut head: Link<T>,
+        mut tail: Link<T>,
+        mut p: Link<T>,
+        psize: usize,
+        q: Link<T>,
+    ) {
+        let mut append = |e: Link<T>| {
+            match tail {
+                Some(t) => (*t.as_ptr()).next = e,
+                None => head = e,
             }
+            tail = e;
+        };
+        for _ in 0..psize {
+            let Some(node) = p else { break };
+            p = (*node.as_ptr()).next;
+            append(Some(node));
+        }
+        match tail {
+            Some(t) => (*t.as_ptr()).next = q,
+            None => head = q,
+        }
+        self.relink_prev(head);
+    }
+
+    // next已经连好了，从head走一遍补上prev和tail
+    unsafe fn relink_prev(&mut self, head: Link<T>) {
+        self.head = head;
+        let mut prev = None;
+        let mut cur = head;
+        while let Some(node) = cur {
+            (*node.as_ptr()).prev = prev;
+            prev = cur;
+            cur = (*node.as_ptr()).next;
+        }
+        self.tail = prev;
+    }
+
+    // 删掉相邻的重复元素，只留第一个
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b)
+    }
+
+    pub fn dedup_by_key<K: PartialEq, F: FnMut(&mut T) -> K>(&mut self, mut key: F) {
+        self.dedup_by(|a, b| key(a) == key(b))
+    }
+
+    // same_bucket(a, b)里a是当前元素，b是前面留下来的那个，返回true就把a删掉
+    pub fn dedup_by<F: FnMut(&mut T, &mut T) -> bool>(&mut self, mut same_bucket: F) {
+        let Some(mut kept) = self.head else {
+            return;
+        };
+        unsafe {
+            while let Some(cur) = (*kept.as_ptr()).next {
+                if same_bucket(&mut (*cur.as_ptr()).elem, &mut (*kept.as_ptr()).elem) {
+                    drop(self.unlink_node(cur));
+                } else {
+                    kept = cur;
+                }
+            }
+        }
+    }
+
+    // 插到第一个比elem大的元素前面，相等的元素之间保持插入顺序
+    pub fn insert_sorted(&mut self, elem: T)
+    where
+        T: Ord,
+    {
+        self.reserve(1);
+        let node = self.new_node(elem);
+        unsafe {
+            let mut next = self.head;
+            while let Some(cur) = next {
+                if (*cur.as_ptr()).elem > (*node.as_ptr()).elem {
+                    break;
+                }
+                next = (*cur.as_ptr()).next;
+            }
+            let prev = match next {
+                Some(next) => (*next.as_ptr()).prev,
+                None => self.tail,
+            };
+            self.link_nodes(prev, next, node, node, 1);
         }
-        f.pad(&dis_str)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// 删除类操作，都是摘节点而不是搬元素，剩下的元素地址不变
+impl<T, A: Allocator> List<T, A> {
+    // idx in range [0, len)
+    pub fn remove(&mut self, idx: usize) -> Option<T> {
+        let node = self.node_at(idx)?;
+        unsafe { Some(self.unlink_node(node)) }
+    }
 
-    #[test]
-    fn get_index_test() {
-        let mut l = List::new();
+    // 交换两个位置上的节点; 越界时panic
+    pub fn swap(&mut self, i: usize, j: usize) {
+        assert!(
+            i < self.length && j < self.length,
+            "swap index out of bounds"
+        );
+        if i == j {
+            return;
+        }
+        let (i, j) = (i.min(j), i.max(j));
+        let a = self.node_at(i).unwrap();
+        let b = self.node_at(j).unwrap();
+        unsafe {
+            let a_prev = (*a.as_ptr()).prev;
+            let a_next = (*a.as_ptr()).next;
+            let b_prev = (*b.as_ptr()).prev;
+            let b_next = (*b.as_ptr()).next;
+            if a_next == Some(b) {
+                // 相邻的只需要把b挪到a前面
+                self.detach_node(b);
+                self.link_nodes(a_prev, Some(a), b, b, 1);
+            } else {
+                self.detach_node(a);
+                self.detach_node(b);
+                self.link_nodes(a_prev, a_next, b, b, 1);
+                self.link_nodes(b_prev, b_next, a, a, 1);
+            }
+        }
+    }
 
-        // 1 -> 2 -> 3
-        l.push_front(3);
-        l.push_front(2);
-        l.push_front(1);
+    // 前n个挪到尾巴上; n > len 时panic
+    pub fn rotate_left(&mut self, n: usize) {
+        assert!(n <= self.length, "rotate index out of bounds");
+        if n == 0 || n == self.length {
+            return;
+        }
+        // 先首尾相连成环，再从新头的前面断开
+        let new_head = self.node_at(n).unwrap();
+        unsafe {
+            let (head, tail) = (self.head.unwrap(), self.tail.unwrap());
+            (*tail.as_ptr()).next = Some(head);
+            (*head.as_ptr()).prev = Some(tail);
+            let new_tail = (*new_head.as_ptr()).prev.unwrap();
+            (*new_tail.as_ptr()).next = None;
+            (*new_head.as_ptr()).prev = None;
+            self.head = Some(new_head);
+            self.tail = Some(new_tail);
+        }
+    }
 
-        assert_eq!(l.get_by_idx(0), Some(&1));
-        assert_eq!(l.get_by_idx(1), Some(&2));
-        assert_eq!(l.get_by_idx(2), Some(&3));
-        assert_eq!(l.get_by_idx(3), None);
+    // 后n个挪到前面; n > len 时panic
+    pub fn rotate_right(&mut self, n: usize) {
+        assert!(n <= self.length, "rotate index out of bounds");
+        self.rotate_left(self.length - n)
     }
 
-    #[test]
-    fn get_mut_index_test() {
-        let mut l = List::new();
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.retain_mut(|elem| f(elem))
+    }
 
-        // 1 -> 2 -> 3
-        l.push_front(3);
-        l.push_front(2);
-        l.push_front(1);
+    pub fn retain_mut<F: FnMut(&mut T) -> bool>(&mut self, mut f: F) {
+        self.extract_if(|elem| !f(elem)).for_each(drop)
+    }
 
-        assert_eq!(l.get_by_idx(3), None);
+    // 区间内的节点一次性摘下来，剩下的两头直接接上; 区间越界时panic
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> IntoIter<T, A>
+    where
+        A: Clone,
+    {
+        let (start, end) = range_bounds(&range, self.length);
+        if start == end {
+            return List::new_in(self.alloc.clone()).into_iter();
+        }
+        let first = self.node_at(start).unwrap();
+        let last = self.node_at(end - 1).unwrap();
+        unsafe { self.unlink_nodes(first, last, end - start).into_iter() }
+    }
 
-        let elem = l.get_mut_by_idx(0);
-        assert_eq!(elem, Some(&mut 1));
-        if let Some(n) = elem {
-            *n = 4;
+    // 惰性地摘掉并返回满足pred的元素，迭代器提前drop时剩下的元素保留
+    pub fn extract_if<F: FnMut(&mut T) -> bool>(&mut self, pred: F) -> ExtractIf<'_, T, F, A> {
+        ExtractIf {
+            cur: self.head,
+            list: self,
+            pred,
         }
-        assert_eq!(l.get_by_idx(0), Some(&4));
     }
+}
 
-    #[test]
-    fn insert_by_idx_test() {
-        let mut l = List::new();
+pub struct ExtractIf<'a, T, F: FnMut(&mut T) -> bool, A: Allocator = Global> {
+    list: &'a mut List<T, A>,
+    cur: Link<T>,
+    pred: F,
+}
 
-        // 1 -> 2 -> 3
-        l.push_front(3);
-        l.push_front(2);
-        l.push_front(1);
+impl<T, F: FnMut(&mut T) -> bool, A: Allocator> Iterator for ExtractIf<'_, T, F, A> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.cur {
+            unsafe {
+                self.cur = (*node.as_ptr()).next;
+                if (self.pred)(&mut (*node.as_ptr()).elem) {
+                    return Some(self.list.unlink_node(node));
+                }
+            }
+        }
+        None
+    }
+}
 
-        // -1 -> 1 -> 2 -> 3
-        l.insert_by_index(0, -1);
+// 指向某个节点的句柄，元素在表里时地址不变，可以不遍历直接删除或挪动
+// 句柄不持有所有权，元素被别的方式删掉以后句柄就悬空了
+pub struct Handle<T> {
+    node: NonNull<Node<T>>,
+}
 
-        // -1 -> 1 -> 2 -> 3 -> 4
-        l.insert_by_index(4, 4);
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
 
-        // -1 -> 1 -> 7 -> 2 -> 3 -> 4
-        l.insert_by_index(2, 7);
+impl<T> Copy for Handle<T> {}
 
-        // -1 -> 1 -> 7 -> 2 -> 3 -> 4
-        l.insert_by_index(7, 4);
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
 
-        assert_eq!(l.get_by_idx(0), Some(&-1));
-        assert_eq!(l.get_by_idx(1), Some(&1));
-        assert_eq!(l.get_by_idx(2), Some(&7));
-        assert_eq!(l.get_by_idx(3), Some(&2));
-        assert_eq!(l.get_by_idx(4), Some(&3));
-        assert_eq!(l.get_by_idx(5), Some(&4));
-        assert_eq!(l.get_by_idx(6), None);
+impl<T> Eq for Handle<T> {}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Handle").field(&self.node).finish()
+    }
+}
+
+impl<T, A: Allocator> List<T, A> {
+    pub fn push_back_handle(&mut self, elem: T) -> Handle<T> {
+        self.reserve(1);
+        let node = self.new_node(elem);
+        unsafe { self.link_nodes(self.tail, None, node, node, 1) };
+        Handle { node }
     }
 
-    #[test]
-    fn same_address_test() {
-        let mut new_node = Box::new(Node::new(3));
-        let new_node_ptr2 = NonNull::new(&mut *new_node);
-        let new_node_ptr = NonNull::new(Box::into_raw(new_node));
-        assert_eq!(new_node_ptr, new_node_ptr2);
+    pub fn push_front_handle(&mut self, elem: T) -> Handle<T> {
+        self.reserve(1);
+        let node = self.new_node(elem);
+        unsafe { self.link_nodes(None, self.head, node, node, 1) };
+        Handle { node }
+    }
+
+    /// # Safety
+    ///
+    /// `handle`指向的元素必须还在这个链表里(没有被pop/remove/drain等删掉，没有在有界链表满了时被挤掉或覆盖，也没有被split/append到别的表)
+    pub unsafe fn get(&self, handle: Handle<T>) -> &T {
+        self.check_handle(handle);
+        &(*handle.node.as_ptr()).elem
+    }
+
+    /// # Safety
+    ///
+    /// 同[`List::get`]
+    pub unsafe fn get_mut(&mut self, handle: Handle<T>) -> &mut T {
+        self.check_handle(handle);
+        &mut (*handle.node.as_ptr()).elem
+    }
+
+    /// # Safety
+    ///
+    /// 同[`List::get`]，删除之后`handle`就不能再用了
+    pub unsafe fn remove_handle(&mut self, handle: Handle<T>) -> T {
+        self.check_handle(handle);
+        self.unlink_node(handle.node)
+    }
+
+    /// # Safety
+    ///
+    /// 同[`List::get`]
+    pub unsafe fn move_to_front(&mut self, handle: Handle<T>) {
+        self.check_handle(handle);
+        if self.head != Some(handle.node) {
+            self.detach_node(handle.node);
+            self.link_nodes(None, self.head, handle.node, handle.node, 1);
+        }
+    }
+
+    /// # Safety
+    ///
+    /// 同[`List::get`]
+    pub unsafe fn move_to_back(&mut self, handle: Handle<T>) {
+        self.check_handle(handle);
+        if self.tail != Some(handle.node) {
+            self.detach_node(handle.node);
+            self.link_nodes(self.tail, None, handle.node, handle.node, 1);
+        }
+    }
+
+    // 只在debug下检查，比一下节点上记的owner，O(1)
+    // 要读节点，所以只能查出"节点还活着但在别的表里"; 节点已经释放的handle按safety约定不能传进来
+    fn check_handle(&self, handle: Handle<T>) {
+        #[cfg(debug_assertions)]
+        assert!(
+            unsafe { (*handle.node.as_ptr()).owner } == self.id,
+            "handle does not belong to this list"
+        );
+        #[cfg(not(debug_assertions))]
+        let _ = handle;
+    }
+}
+
+impl<T, A: Allocator> Drop for List<T, A> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+// 和std::collections::LinkedList一样: 节点只被这个List拥有，所以跟着T走
+unsafe impl<T: Send, A: Allocator + Send> Send for List<T, A> {}
+unsafe impl<T: Sync, A: Allocator + Sync> Sync for List<T, A> {}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 1=>2=>3
+impl<T: Debug, A: Allocator> Display for List<T, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut dis_str = String::from("");
+        for (i, elem) in self.iter().enumerate() {
+            if i > 0 {
+                dis_str += "=>";
+            }
+            dis_str += format!("{:?}", elem).as_str();
+        }
+        f.pad(&dis_str)
+    }
+}
+
+impl<T: Debug, A: Allocator> Debug for List<T, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T, A: Allocator> List<T, A> {
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            head: self.head,
+            tail: self.tail,
+            len: self.length,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            head: self.head,
+            tail: self.tail,
+            len: self.length,
+            _marker: PhantomData,
+        }
+    }
+}
+
+// 两头往中间走，用len判断有没有相遇，不用比较指针
+pub struct Iter<'a, T> {
+    head: Link<T>,
+    tail: Link<T>,
+    len: usize,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.head.map(|node| unsafe {
+            self.len -= 1;
+            self.head = (*node.as_ptr()).next;
+            &(*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.tail.map(|node| unsafe {
+            self.len -= 1;
+            self.tail = (*node.as_ptr()).prev;
+            &(*node.as_ptr()).elem
+        })
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+impl<T> FusedIterator for Iter<'_, T> {}
+
+impl<T> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Iter { ..*self }
+    }
+}
+
+pub struct IterMut<'a, T> {
+    head: Link<T>,
+    tail: Link<T>,
+    len: usize,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.head.map(|node| unsafe {
+            self.len -= 1;
+            self.head = (*node.as_ptr()).next;
+            &mut (*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IterMut<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.tail.map(|node| unsafe {
+            self.len -= 1;
+            self.tail = (*node.as_ptr()).prev;
+            &mut (*node.as_ptr()).elem
+        })
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+impl<T> FusedIterator for IterMut<'_, T> {}
+
+pub struct IntoIter<T, A: Allocator = Global>(List<T, A>);
+
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.length, Some(self.0.length))
+    }
+}
+
+impl<T, A: Allocator> DoubleEndedIterator for IntoIter<T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T, A: Allocator> ExactSizeIterator for IntoIter<T, A> {}
+impl<T, A: Allocator> FusedIterator for IntoIter<T, A> {}
+
+impl<T, A: Allocator> IntoIterator for List<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a List<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a mut List<T, A> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut l = List::new();
+        l.extend(iter);
+        l
+    }
+}
+
+impl<T, A: Allocator> Extend<T> for List<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a, A: Allocator> Extend<&'a T> for List<T, A> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for List<T, A> {
+    fn clone(&self) -> Self {
+        let mut l = List::new_in(self.alloc.clone());
+        l.capacity = self.capacity;
+        l.policy = self.policy;
+        l.extend(self.iter().cloned());
+        l
+    }
+}
+
+impl<T: PartialEq, A: Allocator> PartialEq for List<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.length == other.length && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq, A: Allocator> Eq for List<T, A> {}
+
+impl<T: PartialOrd, A: Allocator> PartialOrd for List<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord, A: Allocator> Ord for List<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+// 先写长度，这样[[1], [2]]和[[1, 2]]的hash不一样
+impl<T: Hash, A: Allocator> Hash for List<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.length);
+        for elem in self {
+            elem.hash(state);
+        }
+    }
+}
+
+impl<T, A: Allocator> List<T, A> {
+    pub fn cursor_front(&self) -> Cursor<'_, T, A> {
+        Cursor {
+            index: 0,
+            current: self.head,
+            list: self,
+        }
+    }
+
+    pub fn cursor_back(&self) -> Cursor<'_, T, A> {
+        Cursor {
+            index: self.length.saturating_sub(1),
+            current: self.tail,
+            list: self,
+        }
+    }
+
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T, A> {
+        CursorMut {
+            index: 0,
+            current: self.head,
+            list: self,
+        }
+    }
+
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T, A> {
+        CursorMut {
+            index: self.length.saturating_sub(1),
+            current: self.tail,
+            list: self,
+        }
+    }
+}
+
+// 和std的LinkedList cursor一样，头尾之间有一个"幽灵"位置(current为None, index为len)
+// 从幽灵位置move_next回到头，move_prev回到尾
+pub struct Cursor<'a, T, A: Allocator = Global> {
+    index: usize,
+    current: Link<T>,
+    list: &'a List<T, A>,
+}
+
+impl<'a, T, A: Allocator> Cursor<'a, T, A> {
+    pub fn index(&self) -> Option<usize> {
+        self.current.map(|_| self.index)
+    }
+
+    pub fn move_next(&mut self) {
+        match self.current {
+            Some(cur) => unsafe {
+                self.current = (*cur.as_ptr()).next;
+                self.index += 1;
+            },
+            None => {
+                self.current = self.list.head;
+                self.index = 0;
+            }
+        }
+    }
+
+    pub fn move_prev(&mut self) {
+        match self.current {
+            Some(cur) => unsafe {
+                self.current = (*cur.as_ptr()).prev;
+                self.index = self.index.checked_sub(1).unwrap_or(self.list.length);
+            },
+            None => {
+                self.current = self.list.tail;
+                self.index = self.list.length.saturating_sub(1);
+            }
+        }
+    }
+
+    pub fn current(&self) -> Option<&'a T> {
+        unsafe { self.current.map(|node| &(*node.as_ptr()).elem) }
+    }
+
+    pub fn peek_next(&self) -> Option<&'a T> {
+        unsafe {
+            let next = match self.current {
+                Some(cur) => (*cur.as_ptr()).next,
+                None => self.list.head,
+            };
+            next.map(|node| &(*node.as_ptr()).elem)
+        }
+    }
+
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        unsafe {
+            let prev = match self.current {
+                Some(cur) => (*cur.as_ptr()).prev,
+                None => self.list.tail,
+            };
+            prev.map(|node| &(*node.as_ptr()).elem)
+        }
+    }
+}
+
+pub struct CursorMut<'a, T, A: Allocator = Global> {
+    index: usize,
+    current: Link<T>,
+    list: &'a mut List<T, A>,
+}
+
+impl<'a, T, A: Allocator> CursorMut<'a, T, A> {
+    pub fn index(&self) -> Option<usize> {
+        self.current.map(|_| self.index)
+    }
+
+    pub fn move_next(&mut self) {
+        match self.current {
+            Some(cur) => unsafe {
+                self.current = (*cur.as_ptr()).next;
+                self.index += 1;
+            },
+            None => {
+                self.current = self.list.head;
+                self.index = 0;
+            }
+        }
+    }
+
+    pub fn move_prev(&mut self) {
+        match self.current {
+            Some(cur) => unsafe {
+                self.current = (*cur.as_ptr()).prev;
+                self.index = self.index.checked_sub(1).unwrap_or(self.list.length);
+            },
+            None => {
+                self.current = self.list.tail;
+                self.index = self.list.length.saturating_sub(1);
+            }
+        }
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        unsafe { self.current.map(|node| &mut (*node.as_ptr()).elem) }
+    }
+
+    // 消耗cursor，拿到和链表借用一样长的引用
+    pub fn into_current(self) -> Option<&'a mut T> {
+        unsafe { self.current.map(|node| &mut (*node.as_ptr()).elem) }
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        unsafe {
+            let next = match self.current {
+                Some(cur) => (*cur.as_ptr()).next,
+                None => self.list.head,
+            };
+            next.map(|node| &mut (*node.as_ptr()).elem)
+        }
+    }
+
+    pub fn peek_prev(&mut self) -> Option<&mut T> {
+        unsafe {
+            let prev = match self.current {
+                Some(cur) => (*cur.as_ptr()).prev,
+                None => self.list.tail,
+            };
+            prev.map(|node| &mut (*node.as_ptr()).elem)
+        }
+    }
+
+    // 只读视图，生命周期跟着这个CursorMut
+    pub fn as_cursor(&self) -> Cursor<'_, T, A> {
+        Cursor {
+            index: self.index,
+            current: self.current,
+            list: self.list,
+        }
+    }
+
+    // 在幽灵位置时插到最前面
+    pub fn insert_after(&mut self, elem: T) {
+        self.list.reserve(1);
+        let node = self.list.new_node(elem);
+        unsafe {
+            let (prev, next) = match self.current {
+                Some(cur) => (Some(cur), (*cur.as_ptr()).next),
+                None => (None, self.list.head),
+            };
+            self.list.link_nodes(prev, next, node, node, 1);
+        }
+        if self.current.is_none() {
+            self.index = self.list.length;
+        }
+    }
+
+    // 在幽灵位置时插到最后面
+    pub fn insert_before(&mut self, elem: T) {
+        self.list.reserve(1);
+        let node = self.list.new_node(elem);
+        unsafe {
+            let prev = match self.current {
+                Some(cur) => (*cur.as_ptr()).prev,
+                None => self.list.tail,
+            };
+            self.list.link_nodes(prev, self.current, node, node, 1);
+        }
+        self.index += 1;
+    }
+
+    // 删掉当前元素，cursor移到下一个
+    pub fn remove_current(&mut self) -> Option<T> {
+        let cur = self.current?;
+        unsafe {
+            self.current = (*cur.as_ptr()).next;
+            Some(self.list.unlink_node(cur))
+        }
+    }
+
+    // 切下当前元素之后的部分; 在幽灵位置时切下整个链表
+    pub fn split_after(&mut self) -> List<T, A>
+    where
+        A: Clone,
+    {
+        let (first, count) = match self.current {
+            Some(cur) => unsafe { ((*cur.as_ptr()).next, self.list.length - self.index - 1) },
+            None => (self.list.head, self.list.length),
+        };
+        let split = match (first, self.list.tail) {
+            (Some(first), Some(last)) => unsafe { self.list.unlink_nodes(first, last, count) },
+            _ => List::new_in(self.list.alloc.clone()),
+        };
+        if self.current.is_none() {
+            self.index = 0;
+        }
+        split
+    }
+
+    // 切下当前元素之前的部分; 在幽灵位置时切下整个链表
+    pub fn split_before(&mut self) -> List<T, A>
+    where
+        A: Clone,
+    {
+        let (last, count) = match self.current {
+            Some(cur) => unsafe { ((*cur.as_ptr()).prev, self.index) },
+            None => (self.list.tail, self.list.length),
+        };
+        let split = match (self.list.head, last) {
+            (Some(first), Some(last)) => unsafe { self.list.unlink_nodes(first, last, count) },
+            _ => List::new_in(self.list.alloc.clone()),
+        };
+        self.index = 0;
+        split
+    }
+}
+
+// 和List::append一样，只有Global的链表能把节点接进来
+impl<T> CursorMut<'_, T> {
+    pub fn splice_after(&mut self, mut other: List<T>) {
+        self.list.reserve(other.length);
+        let Some((first, last, count)) = other.take_nodes() else {
+            return;
+        };
+        unsafe {
+            let (prev, next) = match self.current {
+                Some(cur) => (Some(cur), (*cur.as_ptr()).next),
+                None => (None, self.list.head),
+            };
+            self.list.link_nodes(prev, next, first, last, count);
+        }
+        if self.current.is_none() {
+            self.index = self.list.length;
+        }
+    }
+
+    pub fn splice_before(&mut self, mut other: List<T>) {
+        self.list.reserve(other.length);
+        let Some((first, last, count)) = other.take_nodes() else {
+            return;
+        };
+        unsafe {
+            let prev = match self.current {
+                Some(cur) => (*cur.as_ptr()).prev,
+                None => self.list.tail,
+            };
+            self.list.link_nodes(prev, self.current, first, last, count);
+        }
+        self.index += count;
+    }
+}
+
+// 这里的测试要保持miri干净，改了unsafe代码以后跑一遍:
+//   cargo +nightly miri test --lib fourth::
+// 依赖epoch/多线程的测试(epoch、chase_lev)在miri下标了ignore，太慢
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::LimitAlloc;
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn get_index_test() {
+        let mut l = List::new();
+
+        // 1 -> 2 -> 3
+        l.push_front(3);
+        l.push_front(2);
+        l.push_front(1);
+
+        assert_eq!(l.get_by_idx(0), Some(&1));
+        assert_eq!(l.get_by_idx(1), Some(&2));
+        assert_eq!(l.get_by_idx(2), Some(&3));
+        assert_eq!(l.get_by_idx(3), None);
+    }
+
+    #[test]
+    fn get_mut_index_test() {
+        let mut l = List::new();
+
+        // 1 -> 2 -> 3
+        l.push_front(3);
+        l.push_front(2);
+        l.push_front(1);
+
+        assert_eq!(l.get_by_idx(3), None);
+
+        let elem = l.get_mut_by_idx(0);
+        assert_eq!(elem, Some(&mut 1));
+        if let Some(n) = elem {
+            *n = 4;
+        }
+        assert_eq!(l.get_by_idx(0), Some(&4));
+    }
+
+    #[test]
+    fn insert_by_idx_test() {
+        let mut l = List::new();
+
+        // 1 -> 2 -> 3
+        l.push_front(3);
+        l.push_front(2);
+        l.push_front(1);
+
+        // -1 -> 1 -> 2 -> 3
+        l.insert_by_index(0, -1);
+
+        // -1 -> 1 -> 2 -> 3 -> 4
+        l.insert_by_index(4, 4);
+
+        // -1 -> 1 -> 7 -> 2 -> 3 -> 4
+        l.insert_by_index(2, 7);
+
+        // -1 -> 1 -> 7 -> 2 -> 3 -> 4
+        l.insert_by_index(7, 4);
+
+        assert_eq!(l.get_by_idx(0), Some(&-1));
+        assert_eq!(l.get_by_idx(1), Some(&1));
+        assert_eq!(l.get_by_idx(2), Some(&7));
+        assert_eq!(l.get_by_idx(3), Some(&2));
+        assert_eq!(l.get_by_idx(4), Some(&3));
+        assert_eq!(l.get_by_idx(5), Some(&4));
+        assert_eq!(l.get_by_idx(6), None);
+    }
+
+    #[test]
+    fn same_address_test() {
+        let mut new_node = Box::new(Node::new(3));
+        let new_node_ptr2 = NonNull::new(&mut *new_node);
+        let new_node_ptr = NonNull::new(Box::into_raw(new_node));
+        assert_eq!(new_node_ptr, new_node_ptr2);
+        unsafe { drop(Box::from_raw(new_node_ptr.unwrap().as_ptr())) }
+    }
+
+    #[test]
+    fn basics() {
+        let mut l = List::default();
+        assert_eq!(l.pop_front(), None);
+        assert_eq!(l.pop_back(), None);
+
+        l.push_front(2);
+        l.push_front(1);
+        l.push_back(3);
+        l.push_back(4);
+
+        assert_eq!(l.pop_back(), Some(4));
+        assert_eq!(l.pop_back(), Some(3));
+        l.push_back(5);
+        assert_eq!(l.pop_back(), Some(5));
+        assert_eq!(l.pop_front(), Some(1));
+        assert_eq!(l.pop_back(), Some(2));
+        assert_eq!(l.pop_back(), None);
+        assert_eq!(l.pop_front(), None);
+
+        // 空了以后两头都还能用
+        l.push_back(6);
+        l.push_front(7);
+        assert_eq!(l.pop_front(), Some(7));
+        assert_eq!(l.pop_front(), Some(6));
+        assert_eq!(l.peek_back(), None);
+    }
+
+    #[test]
+    fn peek_test() {
+        let mut l = List::new();
+        assert_eq!(l.peek_front(), None);
+        assert_eq!(l.peek_back_mut(), None);
+
+        l.push_back(1);
+        l.push_back(2);
+        l.push_back(3);
+        assert_eq!(l.peek_front(), Some(&1));
+        assert_eq!(l.peek_back(), Some(&3));
+
+        if let Some(n) = l.peek_front_mut() {
+            *n = 10;
+        }
+        if let Some(n) = l.peek_back_mut() {
+            *n = 30;
+        }
+        assert_eq!(l.pop_front(), Some(10));
+        assert_eq!(l.pop_back(), Some(30));
+        assert_eq!(l.peek_front(), l.peek_back());
+    }
+
+    #[test]
+    fn display_test() {
+        let mut l = List::new();
+        l.push_back(1);
+        l.push_back(2);
+        assert_eq!(l.to_string(), "1=>2");
+        assert_eq!(format!("{:?}", l), "[1, 2]");
+        assert_eq!(List::<i32>::new().to_string(), "");
+    }
+
+    fn collect<T: Clone>(l: &List<T>) -> Vec<T> {
+        let mut v = vec![];
+        let mut c = l.cursor_front();
+        while let Some(e) = c.current() {
+            v.push(e.clone());
+            c.move_next();
+        }
+        v
+    }
+
+    fn from_vec<T>(v: Vec<T>) -> List<T> {
+        let mut l = List::new();
+        for e in v {
+            l.push_back(e);
+        }
+        l
+    }
+
+    #[test]
+    fn cursor_move_peek_test() {
+        let l = from_vec(vec![1, 2, 3]);
+
+        let mut c = l.cursor_front();
+        assert_eq!(c.current(), Some(&1));
+        assert_eq!(c.peek_next(), Some(&2));
+        assert_eq!(c.peek_prev(), None);
+        assert_eq!(c.index(), Some(0));
+        c.move_prev();
+        assert_eq!(c.current(), None);
+        assert_eq!(c.index(), None);
+        assert_eq!(c.peek_next(), Some(&1));
+        assert_eq!(c.peek_prev(), Some(&3));
+        c.move_next();
+        c.move_next();
+        assert_eq!(c.current(), Some(&2));
+        assert_eq!(c.index(), Some(1));
+
+        let mut c = l.cursor_back();
+        assert_eq!(c.current(), Some(&3));
+        assert_eq!(c.index(), Some(2));
+        c.move_next();
+        assert_eq!(c.current(), None);
+        c.move_next();
+        assert_eq!(c.current(), Some(&1));
+
+        let empty = List::<i32>::new();
+        let mut c = empty.cursor_back();
+        assert_eq!(c.current(), None);
+        c.move_next();
+        c.move_prev();
+        assert_eq!(c.current(), None);
+        assert_eq!(c.peek_next(), None);
+    }
+
+    #[test]
+    fn cursor_mut_insert_remove_test() {
+        let mut l = from_vec(vec![1, 2, 3]);
+        let mut c = l.cursor_front_mut();
+        c.move_next();
+        *c.current().unwrap() = 20;
+        c.insert_before(15);
+        c.insert_after(25);
+        assert_eq!(c.index(), Some(2));
+        assert_eq!(c.peek_next(), Some(&mut 25));
+        assert_eq!(c.peek_prev(), Some(&mut 15));
+
+        assert_eq!(c.remove_current(), Some(20));
+        assert_eq!(c.current(), Some(&mut 25));
+        assert_eq!(c.index(), Some(2));
+        assert_eq!(c.as_cursor().peek_next(), Some(&3));
+
+        // 幽灵位置: insert_after插到头，insert_before插到尾
+        c.move_next();
+        c.move_next();
+        assert_eq!(c.current(), None);
+        c.insert_after(0);
+        c.insert_before(4);
+        c.move_prev();
+        assert_eq!(c.current(), Some(&mut 4));
+        assert_eq!(c.index(), Some(5));
+        assert_eq!(c.remove_current(), Some(4));
+        assert_eq!(c.current(), None);
+        assert_eq!(c.remove_current(), None);
+
+        assert_eq!(collect(&l), vec![0, 1, 15, 25, 3]);
+        assert_eq!(l.peek_back(), Some(&3));
+        assert_eq!(l.get_by_idx(4), Some(&3));
+
+        let mut c = l.cursor_front_mut();
+        while c.remove_current().is_some() {}
+        assert_eq!(l.length, 0);
+        assert_eq!(l.peek_front(), None);
+        assert_eq!(l.peek_back(), None);
+    }
+
+    #[test]
+    fn cursor_mut_splice_split_test() {
+        let mut l = from_vec(vec![1, 2, 3, 4]);
+        let mut c = l.cursor_front_mut();
+        c.move_next();
+        c.splice_after(from_vec(vec![10, 11]));
+        c.splice_before(from_vec(vec![20]));
+        c.splice_before(List::new());
+        assert_eq!(c.current(), Some(&mut 2));
+        assert_eq!(c.index(), Some(2));
+        assert_eq!(collect(&l), vec![1, 20, 2, 10, 11, 3, 4]);
+
+        let mut c = l.cursor_front_mut();
+        c.move_next();
+        c.move_next();
+        let before = c.split_before();
+        assert_eq!(c.index(), Some(0));
+        let after = c.split_after();
+        assert_eq!(collect(&before), vec![1, 20]);
+        assert_eq!(collect(&after), vec![10, 11, 3, 4]);
+        assert_eq!(collect(&l), vec![2]);
+        assert_eq!(after.peek_back(), Some(&4));
+        assert_eq!(before.length, 2);
+        assert_eq!(after.length, 4);
+
+        let mut c = l.cursor_front_mut();
+        c.move_prev();
+        c.splice_after(before);
+        c.splice_before(after);
+        assert_eq!(collect(&l), vec![1, 20, 2, 10, 11, 3, 4]);
+
+        // 幽灵位置切下整个链表
+        let mut c = l.cursor_back_mut();
+        c.move_next();
+        let all = c.split_after();
+        assert_eq!(l.length, 0);
+        assert_eq!(all.length, 7);
+        assert_eq!(all.get_by_idx(6), Some(&4));
+    }
+
+    #[test]
+    fn append_test() {
+        let mut a = from_vec(vec![1, 2]);
+        let mut b = from_vec(vec![3, 4, 5]);
+        a.append(&mut b);
+        assert_eq!(collect(&a), vec![1, 2, 3, 4, 5]);
+        assert_eq!(a.length, 5);
+        assert_eq!(b.length, 0);
+        assert_eq!(b.peek_front(), None);
+
+        let mut empty = List::new();
+        a.append(&mut empty);
+        empty.append(&mut a);
+        assert_eq!(collect(&empty), vec![1, 2, 3, 4, 5]);
+        assert_eq!(empty.peek_back(), Some(&5));
+        assert_eq!(a.peek_back(), None);
+
+        b.push_back(6);
+        empty.append(&mut b);
+        assert_eq!(empty.pop_back(), Some(6));
+    }
+
+    #[test]
+    fn split_off_test() {
+        let mut l = from_vec((0..10).collect());
+        let tail = l.split_off(7);
+        assert_eq!(collect(&tail), vec![7, 8, 9]);
+        let mid = l.split_off(2);
+        assert_eq!(collect(&mid), vec![2, 3, 4, 5, 6]);
+        assert_eq!(collect(&l), vec![0, 1]);
+        assert_eq!(l.peek_back(), Some(&1));
+        assert_eq!(mid.peek_front(), Some(&2));
+
+        assert_eq!(l.split_off(2).length, 0);
+        let all = l.split_off(0);
+        assert_eq!(collect(&all), vec![0, 1]);
+        assert_eq!(l.length, 0);
+        assert_eq!(l.peek_front(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_off_out_of_bounds_test() {
+        let mut l = from_vec(vec![1]);
+        l.split_off(2);
+    }
+
+    #[test]
+    fn splice_test() {
+        let mut l = from_vec(vec![1, 5]);
+        l.splice(1, from_vec(vec![2, 3, 4]));
+        l.splice(0, from_vec(vec![0]));
+        l.splice(6, from_vec(vec![6, 7]));
+        l.splice(3, List::new());
+        assert_eq!(collect(&l), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(l.length, 8);
+        assert_eq!(l.get_by_idx(6), Some(&6));
+        assert_eq!(l.peek_back(), Some(&7));
+
+        let mut empty = List::new();
+        empty.splice(0, from_vec(vec![1]));
+        assert_eq!(empty.pop_back(), Some(1));
+    }
+
+    #[test]
+    fn iter_test() {
+        let mut l: List<i32> = (1..=5).collect();
+        let mut iter = l.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&5));
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.clone().collect::<Vec<_>>(), vec![&2, &3, &4]);
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        for e in l.iter_mut() {
+            *e *= 10;
+        }
+        for e in &mut l {
+            *e += 1;
+        }
+        assert_eq!(
+            l.iter().rev().copied().collect::<Vec<_>>(),
+            vec![51, 41, 31, 21, 11]
+        );
+
+        let mut iter = l.iter_mut();
+        assert_eq!(iter.next_back(), Some(&mut 51));
+        assert_eq!(iter.len(), 4);
+
+        let mut into = l.into_iter();
+        assert_eq!(into.next_back(), Some(51));
+        assert_eq!(into.next(), Some(11));
+        assert_eq!(into.len(), 3);
+        assert_eq!(into.collect::<Vec<_>>(), vec![21, 31, 41]);
+    }
+
+    #[test]
+    fn traits_test() {
+        let mut l = List::new();
+        l.extend(vec![1, 2]);
+        l.extend(&[3, 4]);
+        assert_eq!(l.len(), 4);
+        assert!(!l.is_empty());
+
+        let c = l.clone();
+        assert_eq!(l, c);
+        assert_eq!(c, (1..=4).collect());
+        assert_ne!(l, (1..=3).collect());
+        assert!(l < (2..3).collect());
+        assert!(l > (1..=3).collect());
+        assert_eq!(l.cmp(&c), Ordering::Equal);
+
+        let hash = |l: &List<List<i32>>| {
+            let mut h = std::collections::hash_map::DefaultHasher::new();
+            l.hash(&mut h);
+            h.finish()
+        };
+        let a: List<List<i32>> = vec![(1..2).collect(), (2..3).collect()]
+            .into_iter()
+            .collect();
+        let b: List<List<i32>> = vec![(1..3).collect()].into_iter().collect();
+        assert_ne!(hash(&a), hash(&b));
+        assert_eq!(hash(&a), hash(&a.clone()));
+        assert_eq!(format!("{:?}", a), "[[1], [2]]");
+
+        l.clear();
+        assert!(l.is_empty());
+        assert_eq!(l.peek_back(), None);
+    }
+
+    #[test]
+    fn sort_test() {
+        let mut l: List<i32> = List::new();
+        l.sort();
+        l.push_back(1);
+        l.sort();
+        assert_eq!(collect(&l), vec![1]);
+
+        let mut rng = rand::thread_rng();
+        let mut v: Vec<i32> = (0..1000)
+            .map(|_| rand::Rng::gen_range(&mut rng, -100..100))
+            .collect();
+        let mut l: List<i32> = v.iter().copied().collect();
+        let first = l.peek_front().map(|e| e as *const i32);
+        l.sort();
+        v.sort();
+        assert_eq!(collect(&l), v);
+        assert_eq!(
+            l.iter().rev().copied().collect::<Vec<_>>(),
+            v.iter().rev().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(l.len(), 1000);
+        // 节点没有被搬动
+        assert!(l.iter().any(|e| Some(e as *const i32) == first));
+
+        l.sort_by(|a, b| b.cmp(a));
+        v.reverse();
+        assert_eq!(collect(&l), v);
+    }
+
+    #[test]
+    fn sort_panic_test() {
+        for stop in 0..12 {
+            let mut l: List<i32> = [2, 5, 1, 4, 9, 0, 3, 8, 7, 6].into_iter().collect();
+            let mut calls = 0;
+            let r = panic::catch_unwind(AssertUnwindSafe(|| {
+                l.sort_by(|a, b| {
+                    calls += 1;
+                    assert!(calls != stop, "cmp panic");
+                    a.cmp(b)
+                })
+            }));
+            assert_eq!(r.is_err(), stop > 0 && stop <= calls);
+            // 链表还是完整的: 长度对、两个方向走出来一样、元素一个不少
+            assert_eq!(l.len(), 10);
+            assert_eq!(l.iter().count(), 10);
+            let fwd: Vec<_> = l.iter().copied().collect();
+            let mut bwd: Vec<_> = l.iter().rev().copied().collect();
+            bwd.reverse();
+            assert_eq!(fwd, bwd);
+            l.sort();
+            assert_eq!(collect(&l), (0..10).collect::<Vec<_>>());
+        }
+
+        // NaN的partial_cmp().unwrap()
+        let mut l: List<f64> = [1.0, f64::NAN, 0.5, 2.0].into_iter().collect();
+        let r = panic::catch_unwind(AssertUnwindSafe(|| {
+            l.sort_by(|a, b| a.partial_cmp(b).unwrap())
+        }));
+        assert!(r.is_err());
+        assert_eq!(l.iter().count(), 4);
+        assert!(l.pop_back().is_some());
+    }
+
+    #[test]
+    fn sort_stable_test() {
+        let mut l: List<(i32, usize)> = [3, 1, 2, 1, 3, 2, 1].iter().copied().zip(0..).collect();
+        l.sort_by_key(|e| e.0);
+        assert_eq!(
+            collect(&l),
+            vec![(1, 1), (1, 3), (1, 6), (2, 2), (2, 5), (3, 0), (3, 4)]
+        );
+        assert_eq!(l.peek_back(), Some(&(3, 4)));
+    }
+
+    #[test]
+    fn dedup_test() {
+        let mut l: List<i32> = vec![1, 1, 2, 3, 3, 3, 1, 4, 4].into_iter().collect();
+        l.dedup();
+        assert_eq!(collect(&l), vec![1, 2, 3, 1, 4]);
+        assert_eq!(l.len(), 5);
+        assert_eq!(l.peek_back(), Some(&4));
+
+        let mut l: List<i32> = vec![10, 11, 20, 25, 31].into_iter().collect();
+        l.dedup_by_key(|e| *e / 10);
+        assert_eq!(collect(&l), vec![10, 20, 31]);
+
+        let mut l: List<i32> = vec![1, 2, 4, 5, 7].into_iter().collect();
+        l.dedup_by(|a, b| *a - *b == 1);
+        assert_eq!(collect(&l), vec![1, 4, 7]);
+
+        let mut empty = List::<i32>::new();
+        empty.dedup();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn insert_sorted_merge_test() {
+        let mut l = List::new();
+        for e in [5, 1, 3, 9, 3, 0] {
+            l.insert_sorted(e);
+        }
+        assert_eq!(collect(&l), vec![0, 1, 3, 3, 5, 9]);
+        assert_eq!(l.peek_back(), Some(&9));
+
+        let mut a: List<(i32, char)> = vec![(1, 'a'), (3, 'a'), (5, 'a')].into_iter().collect();
+        let mut b: List<(i32, char)> = vec![(0, 'b'), (3, 'b'), (4, 'b'), (8, 'b'), (9, 'b')]
+            .into_iter()
+            .collect();
+        a.merge_by(&mut b, |x, y| x.0.cmp(&y.0));
+        assert!(b.is_empty());
+        assert_eq!(
+            collect(&a),
+            vec![
+                (0, 'b'),
+                (1, 'a'),
+                (3, 'a'),
+                (3, 'b'),
+                (4, 'b'),
+                (5, 'a'),
+                (8, 'b'),
+                (9, 'b')
+            ]
+        );
+        assert_eq!(a.len(), 8);
+        assert_eq!(a.iter().next_back(), Some(&(9, 'b')));
+
+        let mut c = List::new();
+        let mut d: List<i32> = (0..3).collect();
+        c.merge(&mut d);
+        assert_eq!(collect(&c), vec![0, 1, 2]);
+        c.merge(&mut d);
+        assert_eq!(c.len(), 3);
+    }
+
+    #[test]
+    fn remove_swap_test() {
+        let mut l: List<i32> = (0..6).collect();
+        let addr = |l: &List<i32>, v: i32| l.iter().find(|e| **e == v).map(|e| e as *const i32);
+        let three = addr(&l, 3);
+
+        assert_eq!(l.remove(6), None);
+        assert_eq!(l.remove(0), Some(0));
+        assert_eq!(l.remove(4), Some(5));
+        assert_eq!(l.remove(1), Some(2));
+        assert_eq!(collect(&l), vec![1, 3, 4]);
+        assert_eq!(l.peek_back(), Some(&4));
+
+        l.extend([5, 6, 7]);
+        l.swap(0, 5);
+        assert_eq!(collect(&l), vec![7, 3, 4, 5, 6, 1]);
+        l.swap(2, 1);
+        assert_eq!(collect(&l), vec![7, 4, 3, 5, 6, 1]);
+        l.swap(4, 2);
+        l.swap(3, 3);
+        assert_eq!(collect(&l), vec![7, 4, 6, 5, 3, 1]);
+        assert_eq!(
+            l.iter().rev().copied().collect::<Vec<_>>(),
+            vec![1, 3, 5, 6, 4, 7]
+        );
+        assert_eq!(addr(&l, 3), three);
+    }
+
+    #[test]
+    fn handle_test() {
+        let mut l: List<i32> = (1..4).collect();
+        let a = l.push_back_handle(10);
+        let b = l.push_front_handle(0);
+        let c = l.push_back_handle(20);
+        unsafe {
+            assert_eq!(*l.get(a), 10);
+            *l.get_mut(c) += 1;
+            l.move_to_front(a);
+            assert_eq!(collect(&l), vec![10, 0, 1, 2, 3, 21]);
+            l.move_to_back(b);
+            l.move_to_back(b);
+            assert_eq!(collect(&l), vec![10, 1, 2, 3, 21, 0]);
+            l.move_to_front(c);
+            assert_eq!(l.remove_handle(a), 10);
+            assert_eq!(collect(&l), vec![21, 1, 2, 3, 0]);
+            assert_eq!(l.remove_handle(b), 0);
+            assert_eq!(l.peek_back(), Some(&3));
+            assert_eq!(
+                l.iter().rev().copied().collect::<Vec<_>>(),
+                vec![3, 2, 1, 21]
+            );
+            assert_eq!(l.remove_handle(c), 21);
+        }
+        assert_eq!(l.len(), 3);
+
+        // 节点整串挪到别的链表以后，handle跟着节点走
+        let mut other = List::new();
+        let h = other.push_back_handle(7);
+        other.push_back(8);
+        l.append(&mut other);
+        unsafe {
+            l.move_to_front(h);
+            assert_eq!(*l.get(h), 7);
+        }
+        let mut tail = l.split_off(0);
+        unsafe { assert_eq!(tail.remove_handle(h), 7) };
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "handle does not belong to this list")]
+    fn handle_after_split_test() {
+        let mut l: List<i32> = (0..4).collect();
+        let h = l.push_back_handle(4);
+        let _tail = l.split_off(2);
+        unsafe { l.get(h) };
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "handle does not belong to this list")]
+    fn handle_wrong_list_test() {
+        let mut l = List::new();
+        let mut other = List::new();
+        let h = other.push_back_handle(1);
+        l.push_back(1);
+        unsafe { l.get(h) };
+    }
+
+    #[test]
+    fn rotate_test() {
+        let mut l: List<i32> = (0..5).collect();
+        l.rotate_left(2);
+        assert_eq!(collect(&l), vec![2, 3, 4, 0, 1]);
+        l.rotate_right(3);
+        assert_eq!(collect(&l), vec![4, 0, 1, 2, 3]);
+        l.rotate_left(0);
+        l.rotate_right(5);
+        assert_eq!(collect(&l), vec![4, 0, 1, 2, 3]);
+        assert_eq!(l.peek_back(), Some(&3));
+        assert_eq!(l.iter().next_back(), Some(&3));
+        assert_eq!(l.iter().rev().nth(4), Some(&4));
+
+        let mut empty = List::<i32>::new();
+        empty.rotate_left(0);
+    }
+
+    #[test]
+    fn retain_drain_extract_test() {
+        let mut l: List<i32> = (0..10).collect();
+        l.retain(|e| e % 3 != 0);
+        assert_eq!(collect(&l), vec![1, 2, 4, 5, 7, 8]);
+        l.retain_mut(|e| {
+            *e *= 10;
+            *e != 80
+        });
+        assert_eq!(collect(&l), vec![10, 20, 40, 50, 70]);
+        assert_eq!(l.len(), 5);
+
+        let drained: Vec<_> = l.drain(1..3).collect();
+        assert_eq!(drained, vec![20, 40]);
+        assert_eq!(collect(&l), vec![10, 50, 70]);
+        assert_eq!(l.drain(..0).count(), 0);
+        assert_eq!(l.drain(2..).collect::<Vec<_>>(), vec![70]);
+        assert_eq!(l.peek_back(), Some(&50));
+        assert_eq!(l.drain(..).len(), 2);
+        assert!(l.is_empty());
+
+        let mut l: List<i32> = (0..10).collect();
+        {
+            let mut evens = l.extract_if(|e| *e % 2 == 0);
+            assert_eq!(evens.next(), Some(0));
+            assert_eq!(evens.next(), Some(2));
+        }
+        assert_eq!(collect(&l), vec![1, 3, 4, 5, 6, 7, 8, 9]);
+        let big: Vec<_> = l.extract_if(|e| *e > 6).collect();
+        assert_eq!(big, vec![7, 8, 9]);
+        assert_eq!(l.peek_back(), Some(&6));
+        assert_eq!(l.len(), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "range out of bounds")]
+    fn drain_out_of_bounds_test() {
+        let mut l: List<i32> = (0..3).collect();
+        l.drain(2..4);
+    }
+
+    #[test]
+    #[should_panic(expected = "range end overflow")]
+    fn drain_end_overflow_test() {
+        let mut l: List<i32> = (0..3).collect();
+        l.drain(1..=usize::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "range start overflow")]
+    fn drain_start_overflow_test() {
+        let mut l: List<i32> = (0..3).collect();
+        l.drain((Bound::Excluded(usize::MAX), Bound::Unbounded));
+    }
+
+    #[derive(Debug)]
+    struct DropCounter(Rc<Cell<usize>>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn drop_test() {
+        let dropped = Rc::new(Cell::new(0));
+        let mut l = List::new();
+        for _ in 0..10 {
+            l.push_back(DropCounter(dropped.clone()));
+        }
+        l.insert_by_index(5, DropCounter(dropped.clone()));
+        drop(l.pop_back());
+        assert_eq!(dropped.get(), 1);
+        drop(l);
+        assert_eq!(dropped.get(), 11);
+    }
+
+    #[test]
+    fn allocator_test() {
+        let alloc = LimitAlloc::new(4);
+        let mut l = List::new_in(&alloc);
+        assert_eq!(l.try_push_back(1), Ok(None));
+        assert_eq!(l.try_push_front(0), Ok(None));
+        l.push_back(2);
+        l.insert_by_index(3, 3);
+        assert_eq!(alloc.live(), 4);
+        assert_eq!(l.try_push_back(4), Err(4));
+        assert_eq!(l.try_push_front(-1), Err(-1));
+
+        assert_eq!(l.pop_front(), Some(0));
+        assert_eq!(alloc.live(), 3);
+        assert_eq!(l.try_push_back(4), Ok(None));
+
+        let mut tail = l.split_off(2);
+        assert!(std::ptr::eq(*tail.allocator(), &alloc));
+        assert_eq!(tail.pop_back(), Some(4));
+        let c = tail.clone();
+        assert_eq!(alloc.live(), 4);
+        drop(tail);
+        drop(c);
+        assert_eq!(alloc.live(), 2);
+        assert_eq!(l.drain(1..).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(l.peek_back(), Some(&1));
+        drop(l);
+        assert_eq!(alloc.live(), 0);
+    }
+
+    #[test]
+    fn bounded_test() {
+        let mut l = List::bounded(3, OverflowPolicy::Reject);
+        assert_eq!(List::<i32>::new().capacity(), None);
+        assert_eq!(l.capacity(), Some(3));
+        l.extend([1, 2, 3]);
+        assert!(l.is_full());
+        assert_eq!(l.try_push_back(4), Err(4));
+        assert_eq!(l.try_push_front(0), Err(0));
+        assert_eq!(l.pop_back(), Some(3));
+        assert_eq!(l.try_push_front(0), Ok(None));
+        assert_eq!(collect(&l), vec![0, 1, 2]);
+        let c = l.clone();
+        assert_eq!(c.capacity(), Some(3));
+
+        let mut l = List::bounded(3, OverflowPolicy::EvictOpposite);
+        l.extend(0..10);
+        assert_eq!(collect(&l), vec![7, 8, 9]);
+        l.push_front(6);
+        assert_eq!(collect(&l), vec![6, 7, 8]);
+        assert_eq!(l.try_push_back(10), Ok(Some(6)));
+        assert_eq!(l.try_push_front(5), Ok(Some(10)));
+
+        // 当history buffer用: 只留最近的几个，节点一直复用
+        let alloc = LimitAlloc::new(3);
+        let mut l = List::bounded_in(3, OverflowPolicy::OverwriteOldest, &alloc);
+        for i in 0..10u32 {
+            // 挤掉的是三个之前放进去的
+            assert_eq!(l.try_push_back(i), Ok(i.checked_sub(3)));
+        }
+        assert_eq!(alloc.live(), 3);
+        assert_eq!(l.iter().copied().collect::<Vec<_>>(), vec![7, 8, 9]);
+        l.push_front(6);
+        assert_eq!(l.iter().copied().collect::<Vec<_>>(), vec![6, 7, 8]);
+        assert_eq!(l.len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "list is full")]
+    fn bounded_push_full_test() {
+        let mut l = List::bounded(2, OverflowPolicy::Reject);
+        l.push_back(1);
+        l.push_back(2);
+        l.push_back(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "list is full")]
+    fn bounded_splice_full_test() {
+        let mut l = List::bounded(3, OverflowPolicy::OverwriteOldest);
+        l.extend([1, 2]);
+        l.splice(1, [3, 4].into_iter().collect());
+    }
+
+    #[test]
+    #[should_panic(expected = "list is full")]
+    fn bounded_insert_sorted_full_test() {
+        let mut l = List::bounded(2, OverflowPolicy::EvictOpposite);
+        l.extend([1, 3]);
+        l.insert_sorted(2);
+    }
+
+    #[test]
+    fn bounded_merge_full_test() {
+        let mut l = List::bounded(3, OverflowPolicy::Reject);
+        l.extend([1, 4]);
+        let mut other: List<_> = [2, 3].into_iter().collect();
+        let r = panic::catch_unwind(AssertUnwindSafe(|| l.merge(&mut other)));
+        assert!(r.is_err());
+        // 检查在动手之前，两边都没被改
+        assert_eq!(collect(&l), vec![1, 4]);
+        assert_eq!(collect(&other), vec![2, 3]);
+
+        let mut other: List<_> = [2].into_iter().collect();
+        l.merge(&mut other);
+        assert_eq!(collect(&l), vec![1, 2, 4]);
+        assert!(other.is_empty());
+    }
+
+    #[test]
+    fn long_list_test() {
+        let mut l = List::new();
+        for i in 0..100000 {
+            l.push_back(i);
+        }
+        drop(l)
+    }
+
+    #[allow(dead_code)]
+    fn assert_properties() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<List<i32>>();
+        is_sync::<List<i32>>();
+
+        // 协变: &'static的list可以当成短生命周期的用
+        fn list_covariant<'a, T>(x: List<&'static T>) -> List<&'a T> {
+            x
+        }
+        fn iter_covariant<'i, 'a, T>(x: Iter<'i, &'static T>) -> Iter<'i, &'a T> {
+            x
+        }
+        fn into_iter_covariant<'a, T>(x: IntoIter<&'static T>) -> IntoIter<&'a T> {
+            x
+        }
     }
 }
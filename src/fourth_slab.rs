@@ -0,0 +1,635 @@
+// 安全实现 双端 队列链表: 节点都放在一个Vec里，用u32下标代替fourth里的NonNull指针
+// 删掉的槽位串成free list留着复用，所以不会每个元素一次分配; 除了IterMut都不需要unsafe
+
+use std::{collections::HashMap, fmt::Debug, iter::FusedIterator, marker::PhantomData};
+
+const NIL: u32 = u32::MAX;
+
+pub struct List<T> {
+    slots: Vec<Slot<T>>,
+    head: u32,
+    tail: u32,
+    free: u32, // 空闲槽位链表的头，用next串起来
+    length: usize,
+    generation: u32, // 每分配一次加一，写进槽位和handle里
+}
+
+struct Slot<T> {
+    elem: Option<T>, // None表示在free list里
+    prev: u32,
+    next: u32,
+    generation: u32,
+}
+
+// 指向某个元素的句柄，元素被删掉(或者compact以后)就失效，不会指到别的元素上
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            head: NIL,
+            tail: NIL,
+            free: NIL,
+            length: 0,
+            generation: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    // slots里的槽位数，包括free list里空着的; 不是Vec的capacity
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn push_front(&mut self, elem: T) -> Handle {
+        let idx = self.alloc(elem);
+        self.link(NIL, self.head, idx);
+        self.handle(idx)
+    }
+
+    pub fn push_back(&mut self, elem: T) -> Handle {
+        let idx = self.alloc(elem);
+        self.link(self.tail, NIL, idx);
+        self.handle(idx)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        match self.head {
+            NIL => None,
+            idx => Some(self.unlink(idx)),
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        match self.tail {
+            NIL => None,
+            idx => Some(self.unlink(idx)),
+        }
+    }
+
+    pub fn peek_front(&self) -> Option<&T> {
+        self.elem(self.head)
+    }
+
+    pub fn peek_back(&self) -> Option<&T> {
+        self.elem(self.tail)
+    }
+
+    pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+        self.elem_mut(self.head)
+    }
+
+    pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+        self.elem_mut(self.tail)
+    }
+
+    // idx in range [0, len)
+    pub fn get_by_idx(&self, idx: usize) -> Option<&T> {
+        self.elem(self.slot_at(idx))
+    }
+
+    pub fn get_mut_by_idx(&mut self, idx: usize) -> Option<&mut T> {
+        let slot = self.slot_at(idx);
+        self.elem_mut(slot)
+    }
+
+    // idx > len 时什么都不做，返回None
+    pub fn insert_by_index(&mut self, idx: usize, elem: T) -> Option<Handle> {
+        if idx > self.length {
+            return None;
+        }
+        let next = self.slot_at(idx);
+        let prev = match next {
+            NIL => self.tail,
+            next => self.slots[next as usize].prev,
+        };
+        let slot = self.alloc(elem);
+        self.link(prev, next, slot);
+        Some(self.handle(slot))
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.check(handle).and_then(|idx| self.elem(idx))
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        self.check(handle).and_then(|idx| self.elem_mut(idx))
+    }
+
+    // handle已经失效时返回None
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        self.check(handle).map(|idx| self.unlink(idx))
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.head = NIL;
+        self.tail = NIL;
+        self.free = NIL;
+        self.length = 0;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    // 按链表顺序把元素搬到slots前面，丢掉free list
+    // 之前所有的handle都会失效，返回旧handle到新handle的对应表，调用方拿它换掉手里存的handle
+    pub fn compact(&mut self) -> HashMap<Handle, Handle> {
+        let mut slots = Vec::with_capacity(self.length);
+        let mut remap = HashMap::with_capacity(self.length);
+        self.generation = self.generation.wrapping_add(1);
+        let mut cur = self.head;
+        while cur != NIL {
+            let slot = &mut self.slots[cur as usize];
+            let idx = slots.len() as u32;
+            let old = Handle {
+                index: cur,
+                generation: slot.generation,
+            };
+            let new = Handle {
+                index: idx,
+                generation: self.generation,
+            };
+            remap.insert(old, new);
+            cur = slot.next;
+            slots.push(Slot {
+                elem: slot.elem.take(),
+                prev: if idx == 0 { NIL } else { idx - 1 },
+                next: idx + 1,
+                generation: self.generation,
+            });
+        }
+        if let Some(last) = slots.last_mut() {
+            last.next = NIL;
+        }
+        self.head = if slots.is_empty() { NIL } else { 0 };
+        self.tail = (slots.len() as u32).wrapping_sub(1);
+        self.free = NIL;
+        self.slots = slots;
+        remap
+    }
+
+    // 同compact，之前的handle都会失效
+    pub fn shrink_to_fit(&mut self) -> HashMap<Handle, Handle> {
+        let remap = self.compact();
+        self.slots.shrink_to_fit();
+        remap
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            list: self,
+            head: self.head,
+            tail: self.tail,
+            len: self.length,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            slots: self.slots.as_mut_ptr(),
+            head: self.head,
+            tail: self.tail,
+            len: self.length,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.retain_mut(|e| f(e))
+    }
+
+    // 顺着链表走，不满足的槽位直接还给free list
+    pub fn retain_mut<F: FnMut(&mut T) -> bool>(&mut self, mut f: F) {
+        let mut cur = self.head;
+        while cur != NIL {
+            let slot = &mut self.slots[cur as usize];
+            let next = slot.next;
+            if !f(slot.elem.as_mut().unwrap()) {
+                self.unlink(cur);
+            }
+            cur = next;
+        }
+    }
+
+    fn handle(&self, idx: u32) -> Handle {
+        Handle {
+            index: idx,
+            generation: self.slots[idx as usize].generation,
+        }
+    }
+
+    fn check(&self, handle: Handle) -> Option<u32> {
+        let slot = self.slots.get(handle.index as usize)?;
+        (slot.elem.is_some() && slot.generation == handle.generation).then_some(handle.index)
+    }
+
+    fn elem(&self, idx: u32) -> Option<&T> {
+        self.slots.get(idx as usize)?.elem.as_ref()
+    }
+
+    fn elem_mut(&mut self, idx: u32) -> Option<&mut T> {
+        self.slots.get_mut(idx as usize)?.elem.as_mut()
+    }
+
+    // 下标为idx的槽位，从离得近的一头找; idx >= len 时返回NIL
+    fn slot_at(&self, idx: usize) -> u32 {
+        if idx >= self.length {
+            return NIL;
+        }
+        if idx > self.length >> 1 {
+            let mut cur = self.tail;
+            for _ in idx + 1..self.length {
+                cur = self.slots[cur as usize].prev;
+            }
+            cur
+        } else {
+            let mut cur = self.head;
+            for _ in 0..idx {
+                cur = self.slots[cur as usize].next;
+            }
+            cur
+        }
+    }
+
+    // 优先从free list里拿槽位
+    fn alloc(&mut self, elem: T) -> u32 {
+        self.generation = self.generation.wrapping_add(1);
+        let slot = Slot {
+            elem: Some(elem),
+            prev: NIL,
+            next: NIL,
+            generation: self.generation,
+        };
+        match self.free {
+            NIL => {
+                assert!(self.slots.len() < NIL as usize, "too many slots");
+                self.slots.push(slot);
+                (self.slots.len() - 1) as u32
+            }
+            idx => {
+                self.free = self.slots[idx as usize].next;
+                self.slots[idx as usize] = slot;
+                idx
+            }
+        }
+    }
+
+    // 把idx接到prev和next之间，要求prev.next == next
+    fn link(&mut self, prev: u32, next: u32, idx: u32) {
+        self.slots[idx as usize].prev = prev;
+        self.slots[idx as usize].next = next;
+        match prev {
+            NIL => self.head = idx,
+            prev => self.slots[prev as usize].next = idx,
+        }
+        match next {
+            NIL => self.tail = idx,
+            next => self.slots[next as usize].prev = idx,
+        }
+        self.length += 1;
+    }
+
+    // 摘下来并把槽位还给free list
+    fn unlink(&mut self, idx: u32) -> T {
+        let slot = &mut self.slots[idx as usize];
+        let (prev, next) = (slot.prev, slot.next);
+        let elem = slot.elem.take().unwrap();
+        slot.prev = NIL;
+        slot.next = self.free;
+        self.free = idx;
+
+        match prev {
+            NIL => self.head = next,
+            prev => self.slots[prev as usize].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.slots[next as usize].prev = prev,
+        }
+        self.length -= 1;
+        elem
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Iter<'a, T> {
+    list: &'a List<T>,
+    head: u32,
+    tail: u32,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = &self.list.slots[self.head as usize];
+        self.head = slot.next;
+        self.len -= 1;
+        slot.elem.as_ref()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = &self.list.slots[self.tail as usize];
+        self.tail = slot.prev;
+        self.len -= 1;
+        slot.elem.as_ref()
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+impl<T> FusedIterator for Iter<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// 和Iter一样顺着下标走; 安全代码没法按下标反复从slots里借出&mut，所以拿着裸指针
+// 链表上每个槽位只出现一次，head和tail相遇(len到0)就停，所以同一个槽位不会借出两次
+pub struct IterMut<'a, T> {
+    slots: *mut Slot<T>,
+    head: u32,
+    tail: u32,
+    len: usize,
+    _marker: PhantomData<&'a mut Slot<T>>,
+}
+
+unsafe impl<T: Send> Send for IterMut<'_, T> {}
+unsafe impl<T: Sync> Sync for IterMut<'_, T> {}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = unsafe { &mut *self.slots.add(self.head as usize) };
+        self.head = slot.next;
+        self.len -= 1;
+        slot.elem.as_mut()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IterMut<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = unsafe { &mut *self.slots.add(self.tail as usize) };
+        self.tail = slot.prev;
+        self.len -= 1;
+        slot.elem.as_mut()
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+impl<T> FusedIterator for IterMut<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.length, Some(self.0.length))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut l = List::new();
+        l.extend(iter);
+        l
+    }
+}
+
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a> Extend<&'a T> for List<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect<T: Clone>(l: &List<T>) -> Vec<T> {
+        l.iter().cloned().collect()
+    }
+
+    #[test]
+    fn basics() {
+        let mut l = List::new();
+        assert_eq!(l.pop_front(), None);
+        assert_eq!(l.pop_back(), None);
+
+        l.push_front(2);
+        l.push_front(1);
+        l.push_back(3);
+        assert_eq!(collect(&l), vec![1, 2, 3]);
+        assert_eq!(l.peek_front(), Some(&1));
+        assert_eq!(l.peek_back(), Some(&3));
+        *l.peek_back_mut().unwrap() = 30;
+        *l.peek_front_mut().unwrap() = 10;
+
+        assert_eq!(l.pop_back(), Some(30));
+        assert_eq!(l.pop_front(), Some(10));
+        assert_eq!(l.pop_front(), Some(2));
+        assert_eq!(l.pop_back(), None);
+        assert!(l.is_empty());
+        assert_eq!(l.peek_front(), None);
+    }
+
+    #[test]
+    fn index_test() {
+        let mut l: List<i32> = (1..=3).collect();
+        l.insert_by_index(0, -1);
+        l.insert_by_index(4, 4);
+        l.insert_by_index(2, 7);
+        assert_eq!(l.insert_by_index(7, 4), None);
+        assert_eq!(collect(&l), vec![-1, 1, 7, 2, 3, 4]);
+        assert_eq!(l.get_by_idx(5), Some(&4));
+        assert_eq!(l.get_by_idx(6), None);
+        *l.get_mut_by_idx(4).unwrap() = 30;
+        assert_eq!(
+            l.iter().rev().copied().collect::<Vec<_>>(),
+            vec![4, 30, 2, 7, 1, -1]
+        );
+        assert_eq!(l.iter().len(), 6);
+    }
+
+    #[test]
+    fn handle_test() {
+        let mut l = List::new();
+        let a = l.push_back("a");
+        let b = l.push_back("b");
+        let c = l.push_front("c");
+        assert_eq!(l.get(a), Some(&"a"));
+        *l.get_mut(b).unwrap() = "bb";
+
+        assert_eq!(l.remove(a), Some("a"));
+        assert_eq!(l.remove(a), None);
+        assert_eq!(l.get(a), None);
+
+        // 复用了a的槽位，但是旧的handle不会指到新元素
+        let d = l.push_back("d");
+        assert_eq!(l.slot_count(), 3);
+        assert_eq!(l.get(a), None);
+        assert_eq!(l.get(d), Some(&"d"));
+        assert_eq!(collect(&l), vec!["c", "bb", "d"]);
+
+        l.pop_front();
+        assert_eq!(l.get(c), None);
+        l.clear();
+        assert_eq!(l.get(b), None);
+        assert_eq!(l.slot_count(), 0);
+    }
+
+    #[test]
+    fn compact_test() {
+        let mut l: List<i32> = (0..100).collect();
+        let h = l.push_front(-1);
+        let h50 = l.insert_by_index(51, 50).unwrap();
+        l.retain(|e| e % 10 == 0);
+        assert_eq!(l.len(), 11);
+        assert_eq!(l.slot_count(), 102);
+
+        let remap = l.shrink_to_fit();
+        assert_eq!(l.slot_count(), 11);
+        assert_eq!(remap.len(), 11);
+        assert_eq!(l.get(h), None);
+        assert!(!remap.contains_key(&h));
+        // 旧handle失效，换成新的以后还指着同一个元素
+        assert_eq!(l.get(h50), None);
+        assert_eq!(l.get(remap[&h50]), Some(&50));
+        l.remove(remap[&h50]);
+        assert_eq!(collect(&l), (0..100).step_by(10).collect::<Vec<_>>());
+        assert_eq!(l.peek_back(), Some(&90));
+        assert_eq!(l.get_by_idx(9), Some(&90));
+
+        l.push_back(100);
+        l.push_front(-10);
+        assert_eq!(l.pop_back(), Some(100));
+        assert_eq!(l.pop_front(), Some(-10));
+
+        let mut empty = List::<i32>::new();
+        empty.compact();
+        empty.push_back(1);
+        assert_eq!(empty.pop_front(), Some(1));
+    }
+
+    #[test]
+    fn iter_traits_test() {
+        let mut l: List<i32> = (1..=4).collect();
+        l.extend([5, 6]);
+        l.extend(&[7]);
+        assert_eq!(format!("{:?}", l), "[1, 2, 3, 4, 5, 6, 7]");
+
+        // 中间删过再插，槽位顺序和链表顺序不一样了
+        let h = l.push_front(0);
+        l.remove(h);
+        l.insert_by_index(3, 30);
+        for e in l.iter_mut() {
+            *e *= 10;
+        }
+        let mut it = (&mut l).into_iter();
+        assert_eq!(it.len(), 8);
+        assert_eq!(it.next_back(), Some(&mut 70));
+        *it.next().unwrap() += 1;
+        assert_eq!(collect(&l), vec![11, 20, 30, 300, 40, 50, 60, 70]);
+
+        l.retain_mut(|e| {
+            *e += 1;
+            *e % 20 != 1
+        });
+        assert_eq!(collect(&l), vec![12, 31, 51, 71]);
+        l.retain(|e| *e > 20);
+        assert_eq!(l.len(), 3);
+        assert_eq!(l.peek_front(), Some(&31));
+
+        let mut it = l.into_iter();
+        assert_eq!(it.next_back(), Some(71));
+        assert_eq!(it.collect::<Vec<_>>(), vec![31, 51]);
+    }
+}
@@ -0,0 +1,600 @@
+// 展开的双端链表: 每个节点放最多N个元素，节点本身用fourth::List串起来
+// 块是直接放在fourth节点里的定长数组，按下标找元素时一次跳过一整块，遍历时也基本是在连续内存上走
+
+use crate::fourth::{self, CursorMut};
+use std::{
+    iter::FusedIterator,
+    mem::{self, MaybeUninit},
+    ptr, slice,
+};
+
+pub struct List<T, const N: usize = 16> {
+    chunks: fourth::List<Chunk<T, N>>,
+    length: usize,
+}
+
+// 定长的内联数组，[start, start + len)是初始化过的，其余都是未初始化的
+// 不用Vec是为了少一次间接: 一个块就是fourth的一个节点，元素和prev/next在同一次分配里
+// 两头都可以留空，插入删除时挪短的那一边，所以在块的两头push/pop都不用整块搬
+struct Chunk<T, const N: usize> {
+    start: usize,
+    len: usize,
+    items: [MaybeUninit<T>; N],
+}
+
+impl<T, const N: usize> Chunk<T, N> {
+    // 空块，之后的元素从start开始放; 往前长的块从N开始，往后长的从0开始
+    fn new(start: usize) -> Self {
+        Self {
+            start,
+            len: 0,
+            items: [const { MaybeUninit::uninit() }; N],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.items.as_ptr().cast::<T>().add(self.start), self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr().add(self.start), self.len) }
+    }
+
+    fn ptr(&mut self) -> *mut T {
+        self.items.as_mut_ptr().cast()
+    }
+
+    // 前面有空位并且idx前面的元素比后面少(或者后面没空位)时，前半段往前挪一格; 否则后半段往后挪
+    fn insert(&mut self, idx: usize, elem: T) {
+        assert!(idx <= self.len && self.len < N);
+        let back_room = self.start + self.len < N;
+        unsafe {
+            if self.start > 0 && (idx < self.len - idx || !back_room) {
+                let p = self.ptr().add(self.start);
+                ptr::copy(p, p.sub(1), idx);
+                self.start -= 1;
+            } else {
+                let p = self.ptr().add(self.start + idx);
+                ptr::copy(p, p.add(1), self.len - idx);
+            }
+            self.ptr().add(self.start + idx).write(elem);
+        }
+        self.len += 1;
+    }
+
+    fn push(&mut self, elem: T) {
+        self.insert(self.len, elem)
+    }
+
+    // 同样挪短的那一边，删头删尾都是O(1)
+    fn remove(&mut self, idx: usize) -> T {
+        assert!(idx < self.len);
+        unsafe {
+            let p = self.ptr().add(self.start);
+            let elem = p.add(idx).read();
+            if idx < self.len - idx - 1 {
+                ptr::copy(p, p.add(1), idx);
+                self.start += 1;
+            } else {
+                ptr::copy(p.add(idx + 1), p.add(idx), self.len - idx - 1);
+            }
+            self.len -= 1;
+            elem
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        (!self.is_empty()).then(|| self.remove(self.len - 1))
+    }
+
+    // 把[at, len)搬到一个新块里
+    fn split_off(&mut self, at: usize) -> Self {
+        assert!(at <= self.len);
+        let mut other = Self::new(0);
+        let n = self.len - at;
+        self.len = at;
+        unsafe { ptr::copy_nonoverlapping(self.ptr().add(self.start + at), other.ptr(), n) };
+        other.len = n;
+        other
+    }
+
+    fn append(&mut self, other: &mut Self) {
+        assert!(self.len + other.len <= N);
+        // 后面放不下就先把自己挪到最前面
+        if self.start + self.len + other.len > N {
+            unsafe {
+                let p = self.ptr();
+                ptr::copy(p.add(self.start), p, self.len);
+            }
+            self.start = 0;
+        }
+        // 先把other的长度清零再搬，之后other drop时不会再drop一遍
+        let n = mem::replace(&mut other.len, 0);
+        unsafe {
+            let src = other.ptr().add(other.start);
+            ptr::copy_nonoverlapping(src, self.ptr().add(self.start + self.len), n)
+        };
+        self.len += n;
+    }
+}
+
+impl<T, const N: usize> Drop for Chunk<T, N> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+impl<T, const N: usize> List<T, N> {
+    pub fn new() -> Self {
+        // 块容量是1的话分裂不出两半，编译期就拒掉
+        const { assert!(N >= 2, "chunk capacity must be at least 2") };
+        Self {
+            chunks: fourth::List::new(),
+            length: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    // 块的个数
+    pub fn chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        if self.chunks.peek_front().is_none_or(|c| c.is_full()) {
+            self.chunks.push_front(Chunk::new(N));
+        }
+        self.chunks.peek_front_mut().unwrap().insert(0, elem);
+        self.length += 1;
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        if self.chunks.peek_back().is_none_or(|c| c.is_full()) {
+            self.chunks.push_back(Chunk::new(0));
+        }
+        self.chunks.peek_back_mut().unwrap().push(elem);
+        self.length += 1;
+    }
+
+    // 和remove一样，头块剩得太少时和后一块合并
+    pub fn pop_front(&mut self) -> Option<T> {
+        let mut cursor = self.chunks.cursor_front_mut();
+        let elem = cursor.current()?.remove(0);
+        Self::merge_underflow(&mut cursor);
+        self.length -= 1;
+        Some(elem)
+    }
+
+    // 尾块剩得太少时并到前一块里
+    pub fn pop_back(&mut self) -> Option<T> {
+        let mut cursor = self.chunks.cursor_back_mut();
+        let elem = cursor.current()?.pop();
+        let len = cursor.current().unwrap().len();
+        if len == 0 {
+            cursor.remove_current();
+        } else if len < N / 2 {
+            cursor.move_prev();
+            Self::merge_next(&mut cursor);
+        }
+        self.length -= 1;
+        elem
+    }
+
+    pub fn peek_front(&self) -> Option<&T> {
+        self.chunks.peek_front().and_then(|c| c.as_slice().first())
+    }
+
+    pub fn peek_back(&self) -> Option<&T> {
+        self.chunks.peek_back().and_then(|c| c.as_slice().last())
+    }
+
+    // idx in range [0, len)
+    pub fn get_by_idx(&self, idx: usize) -> Option<&T> {
+        if idx >= self.length {
+            return None;
+        }
+        if idx > self.length >> 1 {
+            let mut rest = self.length - idx - 1;
+            for chunk in self.chunks.iter().rev() {
+                if rest < chunk.len() {
+                    return chunk.as_slice().get(chunk.len() - rest - 1);
+                }
+                rest -= chunk.len();
+            }
+        } else {
+            let mut rest = idx;
+            for chunk in self.chunks.iter() {
+                if rest < chunk.len() {
+                    return chunk.as_slice().get(rest);
+                }
+                rest -= chunk.len();
+            }
+        }
+        None
+    }
+
+    pub fn get_mut_by_idx(&mut self, idx: usize) -> Option<&mut T> {
+        if idx >= self.length {
+            return None;
+        }
+        let (cursor, offset) = self.locate(idx);
+        cursor.into_current()?.as_mut_slice().get_mut(offset)
+    }
+
+    // 目标块满了就对半分裂，再插到对应的那一半里; idx > len 时什么都不做
+    pub fn insert_by_index(&mut self, idx: usize, elem: T) {
+        if idx > self.length {
+            return;
+        }
+        if idx == self.length {
+            self.push_back(elem);
+            return;
+        }
+        let (mut cursor, mut offset) = self.locate(idx);
+        let chunk = cursor.current().unwrap();
+        if chunk.is_full() {
+            let upper = chunk.split_off(N / 2);
+            cursor.insert_after(upper);
+            if offset > N / 2 {
+                offset -= N / 2;
+                cursor.move_next();
+            }
+        }
+        cursor.current().unwrap().insert(offset, elem);
+        self.length += 1;
+    }
+
+    // 块里剩的太少时和后一块合并(合并后不超过N)
+    pub fn remove(&mut self, idx: usize) -> Option<T> {
+        if idx >= self.length {
+            return None;
+        }
+        let (mut cursor, offset) = self.locate(idx);
+        let elem = cursor.current().unwrap().remove(offset);
+        Self::merge_underflow(&mut cursor);
+        self.length -= 1;
+        Some(elem)
+    }
+
+    // cursor指着刚删过元素的块: 空了就摘掉，剩得太少就和后一块合并
+    fn merge_underflow(cursor: &mut CursorMut<'_, Chunk<T, N>>) {
+        let len = cursor.current().unwrap().len();
+        if len == 0 {
+            cursor.remove_current();
+        } else if len < N / 2 {
+            Self::merge_next(cursor);
+        }
+    }
+
+    // 后一块能整个放进当前块时把它并进来; cursor在幽灵位置时什么都不做
+    fn merge_next(cursor: &mut CursorMut<'_, Chunk<T, N>>) {
+        let Some(len) = cursor.current().map(|c| c.len()) else {
+            return;
+        };
+        if cursor.peek_next().is_some_and(|next| len + next.len() <= N) {
+            cursor.move_next();
+            let mut next = cursor.remove_current().unwrap();
+            cursor.move_prev();
+            cursor.current().unwrap().append(&mut next);
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        Iter {
+            chunks: self.chunks.iter(),
+            front: [].iter(),
+            back: [].iter(),
+            len: self.length,
+        }
+    }
+
+    // 找到第idx个元素所在的块，返回指向它的cursor和块内下标; 从离得近的一头找
+    fn locate(&mut self, idx: usize) -> (CursorMut<'_, Chunk<T, N>>, usize) {
+        if idx > self.length >> 1 {
+            let mut rest = self.length - idx - 1;
+            let mut cursor = self.chunks.cursor_back_mut();
+            while let Some(chunk) = cursor.current() {
+                if rest < chunk.len() {
+                    let offset = chunk.len() - rest - 1;
+                    return (cursor, offset);
+                }
+                rest -= chunk.len();
+                cursor.move_prev();
+            }
+            unreachable!("length out of sync with chunks")
+        } else {
+            let mut rest = idx;
+            let mut cursor = self.chunks.cursor_front_mut();
+            while let Some(chunk) = cursor.current() {
+                if rest < chunk.len() {
+                    return (cursor, rest);
+                }
+                rest -= chunk.len();
+                cursor.move_next();
+            }
+            unreachable!("length out of sync with chunks")
+        }
+    }
+}
+
+impl<T, const N: usize> Default for List<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 两头各拿着一个块的slice迭代器，用完了再从chunks里取下一块
+pub struct Iter<'a, T, const N: usize> {
+    chunks: fourth::Iter<'a, Chunk<T, N>>,
+    front: slice::Iter<'a, T>,
+    back: slice::Iter<'a, T>,
+    len: usize,
+}
+
+impl<'a, T, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(elem) = self.front.next() {
+                self.len -= 1;
+                return Some(elem);
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.front = chunk.as_slice().iter(),
+                None => {
+                    let elem = self.back.next()?;
+                    self.len -= 1;
+                    return Some(elem);
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for Iter<'_, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(elem) = self.back.next_back() {
+                self.len -= 1;
+                return Some(elem);
+            }
+            match self.chunks.next_back() {
+                Some(chunk) => self.back = chunk.as_slice().iter(),
+                None => {
+                    let elem = self.front.next_back()?;
+                    self.len -= 1;
+                    return Some(elem);
+                }
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for Iter<'_, T, N> {}
+impl<T, const N: usize> FusedIterator for Iter<'_, T, N> {}
+
+impl<'a, T, const N: usize> IntoIterator for &'a List<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, N>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for List<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut l = List::new();
+        for elem in iter {
+            l.push_back(elem);
+        }
+        l
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    extern crate test;
+
+    #[test]
+    fn basics() {
+        let mut l: List<i32, 4> = List::new();
+        assert_eq!(l.pop_front(), None);
+        for i in 0..10 {
+            l.push_back(i);
+        }
+        for i in 1..=5 {
+            l.push_front(-i);
+        }
+        assert_eq!(l.len(), 15);
+        assert_eq!(l.chunks(), 5);
+        assert_eq!(l.peek_front(), Some(&-5));
+        assert_eq!(l.peek_back(), Some(&9));
+        assert_eq!(
+            l.iter().copied().collect::<Vec<_>>(),
+            (-5..10).collect::<Vec<_>>()
+        );
+        assert_eq!(l.pop_back(), Some(9));
+        assert_eq!(l.pop_front(), Some(-5));
+        assert_eq!(l.iter().next_back(), Some(&8));
+    }
+
+    #[test]
+    fn insert_split_test() {
+        let mut l: List<i32, 4> = (0..4).collect();
+        assert_eq!(l.chunks(), 1);
+        l.insert_by_index(1, 10);
+        assert_eq!(l.chunks(), 2);
+        l.insert_by_index(5, 11);
+        l.insert_by_index(9, 12);
+        assert_eq!(
+            l.iter().copied().collect::<Vec<_>>(),
+            vec![0, 10, 1, 2, 3, 11]
+        );
+        assert_eq!(l.get_by_idx(1), Some(&10));
+        assert_eq!(l.get_by_idx(5), Some(&11));
+        *l.get_mut_by_idx(4).unwrap() = 30;
+        assert_eq!(l.get_by_idx(4), Some(&30));
+        *l.get_mut_by_idx(1).unwrap() = 100;
+        assert_eq!(l.get_by_idx(1), Some(&100));
+        assert_eq!(l.get_mut_by_idx(6), None);
+    }
+
+    #[test]
+    fn remove_merge_test() {
+        let mut l: List<i32, 4> = (0..10).collect();
+        assert_eq!(l.chunks(), 3);
+        assert_eq!(l.remove(5), Some(5));
+        assert_eq!(l.remove(4), Some(4));
+        assert_eq!(l.chunks(), 3);
+        assert_eq!(l.remove(4), Some(6));
+        // 中间那块只剩一个，和后一块合并
+        assert_eq!(l.chunks(), 2);
+        assert_eq!(
+            l.iter().copied().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 7, 8, 9]
+        );
+        assert_eq!(l.remove(7), None);
+        assert_eq!(l.len(), 7);
+    }
+
+    #[test]
+    fn pop_merge_test() {
+        let mut l: List<i32, 8> = (0..8).collect();
+        l.insert_by_index(5, 100);
+        assert_eq!(l.chunks(), 2);
+        // 头块剩3个，和后面5个的块并成一块
+        assert_eq!(l.pop_front(), Some(0));
+        assert_eq!(l.chunks(), 1);
+        assert_eq!(
+            l.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 100, 5, 6, 7]
+        );
+
+        l.insert_by_index(4, 200);
+        assert_eq!(l.chunks(), 2);
+        assert_eq!(l.pop_back(), Some(7));
+        assert_eq!(l.chunks(), 1);
+        assert_eq!(
+            l.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 200, 100, 5, 6]
+        );
+
+        // 从两头交替push/pop，块的两头都有空位
+        let mut l: List<i32, 4> = List::new();
+        for i in 0..10 {
+            l.push_front(-i);
+            l.push_back(i);
+            if i % 3 == 0 {
+                l.pop_front();
+                l.pop_back();
+            }
+        }
+        assert_eq!(
+            l.iter().copied().collect::<Vec<_>>(),
+            vec![-8, -7, -5, -4, -2, -1, 1, 2, 4, 5, 7, 8]
+        );
+        assert!(l.chunks() <= 4);
+        while l.pop_front().is_some() {}
+        assert_eq!(l.chunks(), 0);
+    }
+
+    #[test]
+    fn drop_test() {
+        use std::rc::Rc;
+
+        let rc = Rc::new(());
+        let mut l: List<Rc<()>, 4> = (0..10).map(|_| rc.clone()).collect();
+        l.insert_by_index(2, rc.clone());
+        drop(l.remove(5));
+        drop(l.remove(5));
+        drop(l.pop_front());
+        assert_eq!(Rc::strong_count(&rc), 1 + l.len());
+        let mut it = l.iter();
+        assert_eq!(it.len(), 8);
+        it.next_back();
+        assert_eq!(it.count(), 7);
+        drop(l);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn random_ops_test() {
+        let mut rng = rand::thread_rng();
+        let mut l: List<u32, 8> = List::new();
+        let mut v = Vec::new();
+        for _ in 0..5000 {
+            let x: u32 = rng.gen();
+            match rng.gen_range(0..6) {
+                0 => {
+                    l.push_back(x);
+                    v.push(x);
+                }
+                1 => {
+                    l.push_front(x);
+                    v.insert(0, x);
+                }
+                2 => {
+                    let idx = rng.gen_range(0..=v.len());
+                    l.insert_by_index(idx, x);
+                    v.insert(idx, x);
+                }
+                3 if !v.is_empty() => {
+                    let idx = rng.gen_range(0..v.len());
+                    assert_eq!(l.remove(idx), Some(v.remove(idx)));
+                }
+                4 => assert_eq!(l.pop_front(), (!v.is_empty()).then(|| v.remove(0))),
+                _ => assert_eq!(l.pop_back(), v.pop()),
+            }
+            assert_eq!(l.len(), v.len());
+        }
+        assert_eq!(l.iter().copied().collect::<Vec<_>>(), v);
+        for (i, e) in v.iter().enumerate() {
+            assert_eq!(l.get_by_idx(i), Some(e));
+        }
+    }
+
+    #[bench]
+    fn get_by_idx_bench(b: &mut test::Bencher) {
+        let l: List<usize> = (0..10000).collect();
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 4999) % 10000;
+            l.get_by_idx(i)
+        })
+    }
+
+    #[bench]
+    fn fourth_get_by_idx_bench(b: &mut test::Bencher) {
+        let l: fourth::List<usize> = (0..10000).collect();
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 4999) % 10000;
+            l.get_by_idx(i)
+        })
+    }
+}
@@ -0,0 +1,578 @@
+// 侵入式双端链表: 链表不拥有节点，prev/next存在用户结构体里的Link字段上
+// 和fourth一样全程用裸指针串Link，再按Adapter给的偏移量换回用户结构体
+
+use std::{
+    cell::Cell,
+    fmt::Debug,
+    marker::{PhantomData, PhantomPinned},
+    pin::Pin,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// 放在用户结构体里的链接字段; PhantomPinned保证挂上链表以后地址不会变
+pub struct Link {
+    prev: Cell<Option<NonNull<Link>>>,
+    next: Cell<Option<NonNull<Link>>>,
+    owner: Cell<usize>, // 所在链表的id，0表示不在任何链表里
+    _pin: PhantomPinned,
+}
+
+impl Link {
+    pub const fn new() -> Self {
+        Self {
+            prev: Cell::new(None),
+            next: Cell::new(None),
+            owner: Cell::new(0),
+            _pin: PhantomPinned,
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.owner.get() != 0
+    }
+
+    fn reset(&self) {
+        self.prev.set(None);
+        self.next.set(None);
+        self.owner.set(0);
+    }
+}
+
+impl Debug for Link {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Link")
+            .field("linked", &self.is_linked())
+            .finish()
+    }
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 告诉链表Link字段在Value里的位置
+///
+/// # Safety
+///
+/// `OFFSET`必须是`Value`里某个`Link`类型字段的偏移量，一般直接写`mem::offset_of!(Value, link)`
+pub unsafe trait Adapter {
+    type Value;
+    const OFFSET: usize;
+
+    fn link(value: &Self::Value) -> &Link {
+        unsafe { Self::link_ptr(NonNull::from(value)).as_ref() }
+    }
+
+    // 链表里存的指针都从整个Value的指针偏移过去，保留对整个Value的provenance，
+    // value()再减回去访问整个Value才合法; 直接用&Link转出来的指针只能碰Link那一段
+    fn link_ptr(value: NonNull<Self::Value>) -> NonNull<Link> {
+        unsafe { value.byte_add(Self::OFFSET).cast() }
+    }
+
+    /// # Safety
+    ///
+    /// `link`必须是某个`Value`里的那个Link字段
+    unsafe fn value<'v>(link: NonNull<Link>) -> &'v Self::Value {
+        &*link.as_ptr().cast::<u8>().sub(Self::OFFSET).cast()
+    }
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+// 'a: 挂在链表上的值至少要活这么久
+pub struct List<'a, A: Adapter> {
+    head: Option<NonNull<Link>>,
+    tail: Option<NonNull<Link>>,
+    length: usize,
+    id: usize,
+    _marker: PhantomData<(&'a A::Value, A)>,
+}
+
+impl<'a, A: Adapter> List<'a, A> {
+    pub fn new() -> Self {
+        Self {
+            head: None,
+            tail: None,
+            length: 0,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    // 只看Link上记的id，O(1)
+    pub fn contains(&self, value: &A::Value) -> bool {
+        A::link(value).owner.get() == self.id
+    }
+
+    // 已经挂在某个链表上时原样还回去
+    pub fn push_front(&mut self, value: Pin<&'a A::Value>) -> Result<(), Pin<&'a A::Value>> {
+        if A::link(&value).is_linked() {
+            return Err(value);
+        }
+        let link = A::link_ptr(NonNull::from(value.get_ref()));
+        unsafe { self.link_between(None, self.head, link) };
+        Ok(())
+    }
+
+    pub fn push_back(&mut self, value: Pin<&'a A::Value>) -> Result<(), Pin<&'a A::Value>> {
+        if A::link(&value).is_linked() {
+            return Err(value);
+        }
+        let link = A::link_ptr(NonNull::from(value.get_ref()));
+        unsafe { self.link_between(self.tail, None, link) };
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// `value`不能已经在某个链表里，并且在'a内或者被摘下来之前不能移动、不能释放
+    pub unsafe fn push_front_unchecked(&mut self, value: NonNull<A::Value>) {
+        self.link_between(None, self.head, A::link_ptr(value));
+    }
+
+    /// # Safety
+    ///
+    /// 同[`List::push_front_unchecked`]
+    pub unsafe fn push_back_unchecked(&mut self, value: NonNull<A::Value>) {
+        self.link_between(self.tail, None, A::link_ptr(value));
+    }
+
+    pub fn pop_front(&mut self) -> Option<Pin<&'a A::Value>> {
+        let link = self.head?;
+        unsafe {
+            self.unlink(link);
+            Some(Pin::new_unchecked(A::value(link)))
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<Pin<&'a A::Value>> {
+        let link = self.tail?;
+        unsafe {
+            self.unlink(link);
+            Some(Pin::new_unchecked(A::value(link)))
+        }
+    }
+
+    pub fn front(&self) -> Option<Pin<&'a A::Value>> {
+        self.head
+            .map(|link| unsafe { Pin::new_unchecked(A::value(link)) })
+    }
+
+    pub fn back(&self) -> Option<Pin<&'a A::Value>> {
+        self.tail
+            .map(|link| unsafe { Pin::new_unchecked(A::value(link)) })
+    }
+
+    // 不在这个链表里时返回false，O(1)
+    pub fn remove(&mut self, value: &A::Value) -> bool {
+        if !self.contains(value) {
+            return false;
+        }
+        unsafe { self.unlink(A::link_ptr(NonNull::from(value))) };
+        true
+    }
+
+    /// # Safety
+    ///
+    /// `value`必须在这个链表里
+    pub unsafe fn remove_unchecked(&mut self, value: NonNull<A::Value>) {
+        self.unlink(A::link_ptr(value));
+    }
+
+    // 摘下所有节点，之后它们可以再挂到别的链表上
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+
+    pub fn iter(&self) -> Iter<'_, 'a, A> {
+        Iter {
+            head: self.head,
+            tail: self.tail,
+            len: self.length,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn cursor_front(&self) -> Cursor<'_, 'a, A> {
+        Cursor {
+            cur: self.head,
+            list: self,
+        }
+    }
+
+    pub fn cursor_back(&self) -> Cursor<'_, 'a, A> {
+        Cursor {
+            cur: self.tail,
+            list: self,
+        }
+    }
+
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, 'a, A> {
+        CursorMut {
+            cur: self.head,
+            list: self,
+        }
+    }
+
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, 'a, A> {
+        CursorMut {
+            cur: self.tail,
+            list: self,
+        }
+    }
+
+    // 把link接到prev和next之间，要求prev.next == next
+    unsafe fn link_between(
+        &mut self,
+        prev: Option<NonNull<Link>>,
+        next: Option<NonNull<Link>>,
+        link: NonNull<Link>,
+    ) {
+        let l = link.as_ref();
+        l.prev.set(prev);
+        l.next.set(next);
+        l.owner.set(self.id);
+        match prev {
+            Some(prev) => prev.as_ref().next.set(Some(link)),
+            None => self.head = Some(link),
+        }
+        match next {
+            Some(next) => next.as_ref().prev.set(Some(link)),
+            None => self.tail = Some(link),
+        }
+        self.length += 1;
+    }
+
+    unsafe fn unlink(&mut self, link: NonNull<Link>) {
+        let l = link.as_ref();
+        let (prev, next) = (l.prev.get(), l.next.get());
+        match prev {
+            Some(prev) => prev.as_ref().next.set(next),
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => next.as_ref().prev.set(prev),
+            None => self.tail = prev,
+        }
+        l.reset();
+        self.length -= 1;
+    }
+}
+
+impl<A: Adapter> Default for List<'_, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 链表没了以后节点还在，要把它们的Link清掉，不然不能再挂到别的链表上
+impl<A: Adapter> Drop for List<'_, A> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+pub struct Iter<'l, 'a, A: Adapter> {
+    head: Option<NonNull<Link>>,
+    tail: Option<NonNull<Link>>,
+    len: usize,
+    _marker: PhantomData<&'l List<'a, A>>,
+}
+
+impl<'a, A: Adapter> Iterator for Iter<'_, 'a, A> {
+    type Item = Pin<&'a A::Value>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.head.map(|link| unsafe {
+            self.len -= 1;
+            self.head = link.as_ref().next.get();
+            Pin::new_unchecked(A::value(link))
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<A: Adapter> DoubleEndedIterator for Iter<'_, '_, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.tail.map(|link| unsafe {
+            self.len -= 1;
+            self.tail = link.as_ref().prev.get();
+            Pin::new_unchecked(A::value(link))
+        })
+    }
+}
+
+impl<A: Adapter> ExactSizeIterator for Iter<'_, '_, A> {}
+
+impl<'l, 'a, A: Adapter> IntoIterator for &'l List<'a, A> {
+    type Item = Pin<&'a A::Value>;
+    type IntoIter = Iter<'l, 'a, A>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// 和fourth的cursor一样，走到头以后停在一个不存在的"幽灵"位置，再走一步回到另一头
+pub struct Cursor<'l, 'a, A: Adapter> {
+    list: &'l List<'a, A>,
+    cur: Option<NonNull<Link>>,
+}
+
+impl<'a, A: Adapter> Cursor<'_, 'a, A> {
+    pub fn move_next(&mut self) {
+        self.cur = match self.cur {
+            Some(link) => unsafe { link.as_ref().next.get() },
+            None => self.list.head,
+        };
+    }
+
+    pub fn move_prev(&mut self) {
+        self.cur = match self.cur {
+            Some(link) => unsafe { link.as_ref().prev.get() },
+            None => self.list.tail,
+        };
+    }
+
+    pub fn current(&self) -> Option<Pin<&'a A::Value>> {
+        self.cur
+            .map(|link| unsafe { Pin::new_unchecked(A::value(link)) })
+    }
+}
+
+pub struct CursorMut<'l, 'a, A: Adapter> {
+    list: &'l mut List<'a, A>,
+    cur: Option<NonNull<Link>>,
+}
+
+impl<'a, A: Adapter> CursorMut<'_, 'a, A> {
+    pub fn move_next(&mut self) {
+        self.cur = match self.cur {
+            Some(link) => unsafe { link.as_ref().next.get() },
+            None => self.list.head,
+        };
+    }
+
+    pub fn move_prev(&mut self) {
+        self.cur = match self.cur {
+            Some(link) => unsafe { link.as_ref().prev.get() },
+            None => self.list.tail,
+        };
+    }
+
+    pub fn current(&self) -> Option<Pin<&'a A::Value>> {
+        self.cur
+            .map(|link| unsafe { Pin::new_unchecked(A::value(link)) })
+    }
+
+    // 摘下当前节点，cursor移到下一个
+    pub fn remove_current(&mut self) -> Option<Pin<&'a A::Value>> {
+        let link = self.cur?;
+        unsafe {
+            self.cur = link.as_ref().next.get();
+            self.list.unlink(link);
+            Some(Pin::new_unchecked(A::value(link)))
+        }
+    }
+
+    // 在幽灵位置时插到表头
+    pub fn insert_after(&mut self, value: Pin<&'a A::Value>) -> Result<(), Pin<&'a A::Value>> {
+        if A::link(&value).is_linked() {
+            return Err(value);
+        }
+        let link = A::link_ptr(NonNull::from(value.get_ref()));
+        unsafe {
+            match self.cur {
+                Some(cur) => self
+                    .list
+                    .link_between(Some(cur), cur.as_ref().next.get(), link),
+                None => self.list.link_between(None, self.list.head, link),
+            }
+        }
+        Ok(())
+    }
+
+    // 在幽灵位置时插到表尾
+    pub fn insert_before(&mut self, value: Pin<&'a A::Value>) -> Result<(), Pin<&'a A::Value>> {
+        if A::link(&value).is_linked() {
+            return Err(value);
+        }
+        let link = A::link_ptr(NonNull::from(value.get_ref()));
+        unsafe {
+            match self.cur {
+                Some(cur) => self
+                    .list
+                    .link_between(cur.as_ref().prev.get(), Some(cur), link),
+                None => self.list.link_between(self.list.tail, None, link),
+            }
+        }
+        Ok(())
+    }
+}
+
+// 指针偏移全靠手算，改完以后跑一遍miri: cargo +nightly miri test --lib intrusive
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{mem, pin::pin};
+
+    #[derive(Debug)]
+    struct Obj {
+        val: i32,
+        link: Link,
+        other: Link,
+    }
+
+    impl Obj {
+        fn new(val: i32) -> Self {
+            Self {
+                val,
+                link: Link::new(),
+                other: Link::new(),
+            }
+        }
+    }
+
+    struct ObjAdapter;
+    unsafe impl Adapter for ObjAdapter {
+        type Value = Obj;
+        const OFFSET: usize = mem::offset_of!(Obj, link);
+    }
+
+    struct OtherAdapter;
+    unsafe impl Adapter for OtherAdapter {
+        type Value = Obj;
+        const OFFSET: usize = mem::offset_of!(Obj, other);
+    }
+
+    fn vals<A: Adapter<Value = Obj>>(l: &List<'_, A>) -> Vec<i32> {
+        l.iter().map(|o| o.val).collect()
+    }
+
+    #[test]
+    fn basics() {
+        let objs: Vec<Pin<Box<Obj>>> = (0..5).map(|i| Box::pin(Obj::new(i))).collect();
+        let mut l = List::<ObjAdapter>::new();
+        assert_eq!(l.pop_front().map(|o| o.val), None);
+        for o in &objs[1..4] {
+            assert!(l.push_back(o.as_ref()).is_ok());
+        }
+        assert!(l.push_front(objs[0].as_ref()).is_ok());
+        assert!(l.push_back(objs[4].as_ref()).is_ok());
+        assert_eq!(vals(&l), vec![0, 1, 2, 3, 4]);
+        assert_eq!(
+            l.iter().rev().map(|o| o.val).collect::<Vec<_>>(),
+            vec![4, 3, 2, 1, 0]
+        );
+
+        // 同一个Link不能挂两次
+        assert!(l.push_back(objs[2].as_ref()).is_err());
+        assert_eq!(l.len(), 5);
+
+        assert!(l.remove(&objs[2]));
+        assert!(!l.remove(&objs[2]));
+        assert!(!objs[2].link.is_linked());
+        assert_eq!(vals(&l), vec![0, 1, 3, 4]);
+        assert_eq!(l.pop_front().map(|o| o.val), Some(0));
+        assert_eq!(l.pop_back().map(|o| o.val), Some(4));
+        assert_eq!(l.front().map(|o| o.val), Some(1));
+        assert_eq!(l.back().map(|o| o.val), Some(3));
+
+        assert!(l.push_back(objs[2].as_ref()).is_ok());
+        assert_eq!(vals(&l), vec![1, 3, 2]);
+        drop(l);
+        assert!(objs.iter().all(|o| !o.link.is_linked()));
+    }
+
+    #[test]
+    fn two_lists_test() {
+        let a = pin!(Obj::new(1));
+        let b = pin!(Obj::new(2));
+        let (a, b) = (a.as_ref(), b.as_ref());
+        let mut l1 = List::<ObjAdapter>::new();
+        let mut l2 = List::<ObjAdapter>::new();
+        let mut l3 = List::<OtherAdapter>::new();
+
+        l1.push_back(a).unwrap();
+        assert!(l2.push_back(a).is_err());
+        assert!(!l2.remove(&a));
+        assert!(l1.contains(&a));
+        assert!(!l2.contains(&a));
+
+        // 另一个Link字段可以同时挂到另一个链表上
+        l3.push_back(b).unwrap();
+        l3.push_front(a).unwrap();
+        l2.push_back(b).unwrap();
+        assert_eq!(vals(&l3), vec![1, 2]);
+        assert!(l3.remove(&b));
+        assert_eq!(vals(&l2), vec![2]);
+        assert_eq!(vals(&l3), vec![1]);
+    }
+
+    #[test]
+    fn unchecked_test() {
+        let mut arena: Vec<Obj> = (0..4).map(Obj::new).collect();
+        let base = NonNull::new(arena.as_mut_ptr()).unwrap();
+        let mut l = List::<ObjAdapter>::new();
+        unsafe {
+            for i in 0..4 {
+                l.push_front_unchecked(base.add(i));
+            }
+            assert_eq!(vals(&l), vec![3, 2, 1, 0]);
+            l.remove_unchecked(base.add(1));
+            l.push_back_unchecked(base.add(1));
+        }
+        assert_eq!(vals(&l), vec![3, 2, 0, 1]);
+        l.clear();
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn cursor_test() {
+        let objs: Vec<Pin<Box<Obj>>> = (0..6).map(|i| Box::pin(Obj::new(i))).collect();
+        let mut l = List::<ObjAdapter>::new();
+        for o in &objs[..3] {
+            l.push_back(o.as_ref()).unwrap();
+        }
+
+        let mut c = l.cursor_front();
+        assert_eq!(c.current().map(|o| o.val), Some(0));
+        c.move_prev();
+        assert!(c.current().is_none());
+        c.move_prev();
+        assert_eq!(c.current().map(|o| o.val), Some(2));
+
+        let mut c = l.cursor_front_mut();
+        c.move_next();
+        c.insert_after(objs[3].as_ref()).unwrap();
+        c.insert_before(objs[4].as_ref()).unwrap();
+        assert!(c.insert_before(objs[0].as_ref()).is_err());
+        assert_eq!(c.remove_current().map(|o| o.val), Some(1));
+        assert_eq!(c.current().map(|o| o.val), Some(3));
+        c.move_next();
+        c.move_next();
+        assert!(c.current().is_none());
+        c.insert_before(objs[5].as_ref()).unwrap();
+        c.insert_after(objs[1].as_ref()).unwrap();
+        assert_eq!(vals(&l), vec![1, 0, 4, 3, 2, 5]);
+        assert_eq!(l.len(), 6);
+    }
+}
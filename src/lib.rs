@@ -1,9 +1,26 @@
+#![feature(allocator_api)]
 #![feature(test)]
+pub mod bloom;
+pub mod chase_lev;
+// epoch/second/third/skiplist_epoch/skiplist_gpt是早期的练习代码，lint先不动它们
+#[allow(clippy::all)]
 pub mod epoch;
+pub mod fifth;
 pub mod first;
 pub mod fourth;
+pub mod fourth_slab;
+pub mod fourth_unrolled;
+pub mod intrusive;
+pub mod lru;
+pub mod rope;
+#[allow(clippy::new_without_default)]
 pub mod second;
 pub mod skiplist;
+#[allow(unused, clippy::new_without_default)]
 pub mod skiplist_epoch;
+#[allow(clippy::new_without_default, clippy::question_mark)]
 pub mod skiplist_gpt;
+#[cfg(test)]
+mod test_util;
+#[allow(dead_code, mismatched_lifetime_syntaxes, clippy::new_without_default)]
 pub mod third;
@@ -0,0 +1,539 @@
+// 基于fourth::List的LRU/LFU缓存: HashMap里存节点句柄，命中以后O(1)把节点挪到表头
+
+use crate::fourth::{self, Handle};
+use std::{borrow::Borrow, collections::HashMap, hash::Hash};
+
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize>;
+type EvictCallback<K, V> = Box<dyn FnMut(K, V)>;
+
+// 表头是最近用过的，表尾是最久没用的
+// map里的每个句柄都指向list里的一个节点，两边始终一一对应，所以句柄不会悬空
+// 节点里记下放进来时算的权重: get_mut之后值可能变了，不能再重新算一遍去减
+pub struct LruCache<K, V> {
+    map: HashMap<K, Handle<(K, V, usize)>>,
+    list: fourth::List<(K, V, usize)>,
+    capacity: usize,
+    max_weight: usize,
+    weight: usize,
+    weigher: Weigher<K, V>,
+    on_evict: Option<EvictCallback<K, V>>,
+}
+
+impl<K: Clone + Hash + Eq, V> LruCache<K, V> {
+    // 按个数淘汰，每个元素的权重都是1
+    pub fn new(capacity: usize) -> Self {
+        Self::with_weigher(capacity, usize::MAX, |_, _| 1)
+    }
+
+    // 个数超过capacity或者总权重超过max_weight时从表尾开始淘汰
+    pub fn with_weigher<F>(capacity: usize, max_weight: usize, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> usize + 'static,
+    {
+        assert!(capacity > 0, "capacity must be positive");
+        Self {
+            map: HashMap::new(),
+            list: fourth::List::new(),
+            capacity,
+            max_weight,
+            weight: 0,
+            weigher: Box::new(weigher),
+            on_evict: None,
+        }
+    }
+
+    // 因为超容量被淘汰时调用; remove/pop_lru/覆盖旧值不会调用
+    pub fn set_evict_callback<F: FnMut(K, V) + 'static>(&mut self, f: F) {
+        self.on_evict = Some(Box::new(f));
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    // 命中时把元素挪成最近使用
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let handle = *self.map.get(key)?;
+        unsafe {
+            self.list.move_to_front(handle);
+            Some(&mut self.list.get_mut(handle).1)
+        }
+    }
+
+    // 只看不改顺序
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let handle = *self.map.get(key)?;
+        unsafe { Some(&self.list.get(handle).1) }
+    }
+
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        self.list.peek_back().map(|(k, v, _)| (k, v))
+    }
+
+    // 返回被覆盖的旧值; 放进去以后超出限制就淘汰
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        let w = (self.weigher)(&key, &value);
+        // 单个元素就超过max_weight时直接当作被淘汰，不去挤掉别的元素
+        if w > self.max_weight {
+            let old = self.remove(&key);
+            if let Some(f) = self.on_evict.as_mut() {
+                f(key, value);
+            }
+            return old;
+        }
+        let old = match self.map.get(&key) {
+            Some(&handle) => unsafe {
+                self.list.move_to_front(handle);
+                let entry = self.list.get_mut(handle);
+                self.weight -= std::mem::replace(&mut entry.2, w);
+                Some(std::mem::replace(&mut entry.1, value))
+            },
+            None => {
+                let handle = self.list.push_front_handle((key.clone(), value, w));
+                self.map.insert(key, handle);
+                None
+            }
+        };
+        self.weight += w;
+        self.evict();
+        old
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let handle = self.map.remove(key)?;
+        let (_, v, w) = unsafe { self.list.remove_handle(handle) };
+        self.weight -= w;
+        Some(v)
+    }
+
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let (k, v, w) = self.list.pop_back()?;
+        self.map.remove(&k);
+        self.weight -= w;
+        Some((k, v))
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.list.clear();
+        self.weight = 0;
+    }
+
+    // 调整容量，变小时立刻淘汰多出来的
+    pub fn resize(&mut self, capacity: usize, max_weight: usize) {
+        assert!(capacity > 0, "capacity must be positive");
+        self.capacity = capacity;
+        self.max_weight = max_weight;
+        self.evict();
+    }
+
+    // 从最近使用到最久没用
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter(self.list.iter())
+    }
+
+    fn evict(&mut self) {
+        while self.map.len() > self.capacity || self.weight > self.max_weight {
+            let Some((k, v)) = self.pop_lru() else {
+                break;
+            };
+            if let Some(f) = self.on_evict.as_mut() {
+                f(k, v);
+            }
+        }
+    }
+}
+
+pub struct Iter<'a, K, V>(fourth::Iter<'a, (K, V, usize)>);
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, v, _)| (k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(k, v, _)| (k, v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<'a, K: Clone + Hash + Eq, V> IntoIterator for &'a LruCache<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// 按访问次数淘汰，次数相同时淘汰其中最久没用的
+// 每个次数一个桶，桶里按最近使用排好; 记住最小的次数，淘汰时直接去那个桶的表尾拿
+pub struct LfuCache<K, V> {
+    map: HashMap<K, LfuEntry<K, V>>,
+    buckets: HashMap<usize, fourth::List<K>>,
+    min_freq: usize,
+    capacity: usize,
+    on_evict: Option<EvictCallback<K, V>>,
+}
+
+struct LfuEntry<K, V> {
+    value: V,
+    freq: usize,
+    handle: Handle<K>,
+}
+
+impl<K: Clone + Hash + Eq, V> LfuCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        Self {
+            map: HashMap::new(),
+            buckets: HashMap::new(),
+            min_freq: 0,
+            capacity,
+            on_evict: None,
+        }
+    }
+
+    pub fn set_evict_callback<F: FnMut(K, V) + 'static>(&mut self, f: F) {
+        self.on_evict = Some(Box::new(f));
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // 访问次数，不存在时返回None
+    pub fn frequency<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key).map(|e| e.freq)
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.map.get_mut(key)?;
+        Self::touch(&mut self.buckets, &mut self.min_freq, entry);
+        Some(&mut entry.value)
+    }
+
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key).map(|e| &e.value)
+    }
+
+    // 覆盖旧值也算一次访问
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(entry) = self.map.get_mut(&key) {
+            Self::touch(&mut self.buckets, &mut self.min_freq, entry);
+            return Some(std::mem::replace(&mut entry.value, value));
+        }
+        if self.map.len() == self.capacity {
+            if let Some((k, v)) = self.pop_lfu() {
+                if let Some(f) = self.on_evict.as_mut() {
+                    f(k, v);
+                }
+            }
+        }
+        let handle = self
+            .buckets
+            .entry(1)
+            .or_default()
+            .push_front_handle(key.clone());
+        self.map.insert(
+            key,
+            LfuEntry {
+                value,
+                freq: 1,
+                handle,
+            },
+        );
+        self.min_freq = 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.map.remove(key)?;
+        let bucket = self.buckets.get_mut(&entry.freq).unwrap();
+        unsafe { bucket.remove_handle(entry.handle) };
+        if bucket.is_empty() {
+            self.buckets.remove(&entry.freq);
+            // min_freq对应的桶空了就重新找一遍，只有remove会走到这里
+            if entry.freq == self.min_freq {
+                self.min_freq = self.buckets.keys().copied().min().unwrap_or(0);
+            }
+        }
+        Some(entry.value)
+    }
+
+    pub fn pop_lfu(&mut self) -> Option<(K, V)> {
+        let bucket = self.buckets.get_mut(&self.min_freq)?;
+        let key = bucket.pop_back().unwrap();
+        if bucket.is_empty() {
+            self.buckets.remove(&self.min_freq);
+            self.min_freq = self.buckets.keys().copied().min().unwrap_or(0);
+        }
+        let entry = self.map.remove(&key).unwrap();
+        Some((key, entry.value))
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.buckets.clear();
+        self.min_freq = 0;
+    }
+
+    // 挪到下一个次数的桶里; 原来的桶空了并且是最小次数时，最小次数跟着加一
+    fn touch(
+        buckets: &mut HashMap<usize, fourth::List<K>>,
+        min_freq: &mut usize,
+        entry: &mut LfuEntry<K, V>,
+    ) {
+        let bucket = buckets.get_mut(&entry.freq).unwrap();
+        let key = unsafe { bucket.remove_handle(entry.handle) };
+        if bucket.is_empty() {
+            buckets.remove(&entry.freq);
+            if *min_freq == entry.freq {
+                *min_freq += 1;
+            }
+        }
+        entry.freq += 1;
+        entry.handle = buckets
+            .entry(entry.freq)
+            .or_default()
+            .push_front_handle(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LfuCache, LruCache};
+    use std::hash::Hash;
+    use std::{cell::RefCell, rc::Rc};
+
+    fn keys<K: Clone + Hash + Eq, V>(c: &LruCache<K, V>) -> Vec<K> {
+        c.iter().map(|(k, _)| k.clone()).collect()
+    }
+
+    #[test]
+    fn lru_basics() {
+        let mut c = LruCache::new(3);
+        assert_eq!(c.put("a", 1), None);
+        c.put("b", 2);
+        c.put("c", 3);
+        assert_eq!(keys(&c), vec!["c", "b", "a"]);
+
+        assert_eq!(c.get("a"), Some(&1));
+        assert_eq!(c.peek("b"), Some(&2));
+        assert_eq!(keys(&c), vec!["a", "c", "b"]);
+        assert_eq!(c.peek_lru(), Some((&"b", &2)));
+
+        c.put("d", 4);
+        assert!(!c.contains("b"));
+        assert_eq!(c.put("c", 30), Some(3));
+        assert_eq!(keys(&c), vec!["c", "d", "a"]);
+        *c.get_mut("a").unwrap() += 10;
+        assert_eq!(c.iter().next_back(), Some((&"d", &4)));
+
+        assert_eq!(c.pop_lru(), Some(("d", 4)));
+        assert_eq!(c.remove("c"), Some(30));
+        assert_eq!(c.remove("c"), None);
+        assert_eq!(keys(&c), vec!["a"]);
+        assert_eq!(c.get("a"), Some(&11));
+        assert_eq!(c.len(), 1);
+        c.clear();
+        assert!(c.is_empty());
+        assert_eq!(c.pop_lru(), None);
+    }
+
+    #[test]
+    fn lru_weight_and_callback() {
+        let evicted = Rc::new(RefCell::new(Vec::<i32>::new()));
+        let mut c = LruCache::with_weigher(10, 10, |_: &i32, v: &String| v.len());
+        let log = evicted.clone();
+        c.set_evict_callback(move |k, _| log.borrow_mut().push(k));
+
+        c.put(1, "aaaa".to_string());
+        c.put(2, "bbbb".to_string());
+        assert_eq!(c.weight(), 8);
+        c.put(3, "cc".to_string());
+        assert_eq!(c.weight(), 10);
+        assert!(evicted.borrow().is_empty());
+
+        c.get(&1);
+        c.put(4, "d".to_string());
+        assert_eq!(*evicted.borrow(), vec![2]);
+        assert_eq!(c.weight(), 7);
+
+        // 覆盖时按新值重新算权重
+        c.put(3, "cccccc".to_string());
+        assert_eq!(c.weight(), 7);
+        assert_eq!(*evicted.borrow(), vec![2, 1]);
+        assert_eq!(keys(&c), vec![3, 4]);
+
+        c.put(5, "x".repeat(11));
+        assert!(!c.contains(&5));
+        assert_eq!(*evicted.borrow(), vec![2, 1, 5]);
+
+        c.resize(1, 10);
+        assert_eq!(keys(&c), vec![3]);
+        assert_eq!(c.remove(&3).as_deref(), Some("cccccc"));
+        assert_eq!(c.weight(), 0);
+    }
+
+    #[test]
+    fn lru_weight_after_get_mut() {
+        let mut c = LruCache::with_weigher(10, 10, |_: &&str, v: &String| v.len());
+        c.put("a", "a".to_string());
+        c.put("b", "bb".to_string());
+        assert_eq!(c.weight(), 3);
+        // 改值不会重新算权重，按放进来时的权重扣
+        c.get_mut("a").unwrap().push_str("aaaa");
+        assert_eq!(c.weight(), 3);
+        assert_eq!(c.remove("a").as_deref(), Some("aaaaa"));
+        assert_eq!(c.weight(), 2);
+
+        c.get_mut("b").unwrap().clear();
+        assert_eq!(c.pop_lru(), Some(("b", String::new())));
+        assert_eq!(c.weight(), 0);
+
+        c.put("c", "c".to_string());
+        c.get_mut("c").unwrap().push_str("cccc");
+        assert_eq!(c.put("c", "cc".to_string()).as_deref(), Some("ccccc"));
+        assert_eq!(c.weight(), 2);
+    }
+
+    #[test]
+    fn lfu_test() {
+        let evicted = Rc::new(RefCell::new(Vec::<(i32, i32)>::new()));
+        let mut c = LfuCache::new(2);
+        let log = evicted.clone();
+        c.set_evict_callback(move |k, v| log.borrow_mut().push((k, v)));
+
+        c.put(1, 1);
+        c.put(2, 2);
+        assert_eq!(c.get(&1), Some(&1));
+        c.put(3, 3);
+        assert_eq!(*evicted.borrow(), vec![(2, 2)]);
+        assert_eq!(c.get(&2), None);
+        assert_eq!(c.get(&3), Some(&3));
+        // 1和3都访问了两次，淘汰较久没用的1
+        c.put(4, 4);
+        assert_eq!(*evicted.borrow(), vec![(2, 2), (1, 1)]);
+        assert_eq!(c.frequency(&3), Some(2));
+        assert_eq!(c.frequency(&4), Some(1));
+
+        assert_eq!(c.put(4, 40), Some(4));
+        assert_eq!(c.peek(&4), Some(&40));
+        assert_eq!(c.frequency(&4), Some(2));
+        c.get(&4);
+        assert_eq!(c.remove(&3), Some(3));
+        assert_eq!(c.pop_lfu(), Some((4, 40)));
+        assert!(c.is_empty());
+        assert_eq!(c.pop_lfu(), None);
+
+        c.put(5, 5);
+        assert_eq!(c.len(), 1);
+        assert_eq!(c.pop_lfu(), Some((5, 5)));
+    }
+
+    #[test]
+    fn lru_matches_model() {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let mut c = LruCache::new(16);
+        let mut model: Vec<(u8, u32)> = Vec::new();
+        for _ in 0..5000 {
+            let k = rng.gen_range(0..32u8);
+            if rng.gen_bool(0.5) {
+                let v = rng.gen();
+                c.put(k, v);
+                model.retain(|(mk, _)| *mk != k);
+                model.insert(0, (k, v));
+                model.truncate(16);
+            } else {
+                let pos = model.iter().position(|(mk, _)| *mk == k);
+                let expect = pos.map(|p| model.remove(p));
+                if let Some(e) = expect {
+                    model.insert(0, e);
+                }
+                assert_eq!(c.get(&k), expect.map(|e| e.1).as_ref());
+            }
+        }
+        assert_eq!(c.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), model);
+    }
+}
@@ -0,0 +1,487 @@
+// 文本缓冲: 字符串切成不超过CHUNK_MAX字节的块，串在fourth::List里
+// 每块缓存自己的字符数和换行数，按位置找块时整块跳过，不用逐字符数
+
+use crate::fourth::{self, CursorMut};
+use std::{fmt::Display, ops::RangeBounds};
+
+const CHUNK_MAX: usize = 512;
+// 小于这个字节数的块算欠满，编辑之后要和左右邻居合并
+const CHUNK_MIN: usize = CHUNK_MAX / 4;
+
+// 位置都按字符(char)算，不是字节
+pub struct Rope {
+    chunks: fourth::List<Chunk>,
+    bytes: usize,
+    chars: usize,
+    newlines: usize,
+}
+
+struct Chunk {
+    text: String,
+    chars: usize,
+    newlines: usize,
+}
+
+impl Chunk {
+    fn new(text: &str) -> Self {
+        let mut s = String::with_capacity(CHUNK_MAX);
+        s.push_str(text);
+        Self {
+            chars: text.chars().count(),
+            newlines: count_newlines(text),
+            text: s,
+        }
+    }
+}
+
+fn count_newlines(s: &str) -> usize {
+    s.bytes().filter(|b| *b == b'\n').count()
+}
+
+// 第chars个字符在s里的字节下标，超出时返回s.len()
+fn byte_offset(s: &str, chars: usize) -> usize {
+    s.char_indices().nth(chars).map_or(s.len(), |(i, _)| i)
+}
+
+// cursor所在的块先和后一块、再和前一块试着合并: 两块里有一块欠满并且合起来放得下就合
+// cursor在幽灵位置时只看最后一块和它前一块
+fn merge_around(cursor: &mut CursorMut<'_, Chunk>) {
+    if cursor.current().is_none() {
+        cursor.move_prev();
+    }
+    merge_next(cursor);
+    if cursor.peek_prev().is_some() {
+        cursor.move_prev();
+        merge_next(cursor);
+    }
+}
+
+fn merge_next(cursor: &mut CursorMut<'_, Chunk>) {
+    let Some(len) = cursor.current().map(|c| c.text.len()) else {
+        return;
+    };
+    let fits = cursor.peek_next().is_some_and(|next| {
+        (len < CHUNK_MIN || next.text.len() < CHUNK_MIN) && len + next.text.len() <= CHUNK_MAX
+    });
+    if fits {
+        cursor.move_next();
+        let next = cursor.remove_current().unwrap();
+        cursor.move_prev();
+        let chunk = cursor.current().unwrap();
+        chunk.text.push_str(&next.text);
+        chunk.chars += next.chars;
+        chunk.newlines += next.newlines;
+    }
+}
+
+// 在字符边界上切成不超过CHUNK_MAX字节的几段
+fn split_pieces(mut s: &str) -> Vec<&str> {
+    let mut pieces = Vec::with_capacity(s.len() / CHUNK_MAX + 1);
+    while s.len() > CHUNK_MAX {
+        let mut at = CHUNK_MAX;
+        while !s.is_char_boundary(at) {
+            at -= 1;
+        }
+        pieces.push(&s[..at]);
+        s = &s[at..];
+    }
+    pieces.push(s);
+    pieces
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self {
+            chunks: fourth::List::new(),
+            bytes: 0,
+            chars: 0,
+            newlines: 0,
+        }
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.chars
+    }
+
+    // 空文本也算一行
+    pub fn len_lines(&self) -> usize {
+        self.newlines + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes == 0
+    }
+
+    // pos > len_chars 时panic
+    pub fn insert(&mut self, pos: usize, text: &str) {
+        assert!(pos <= self.chars, "insert position out of bounds");
+        if text.is_empty() {
+            return;
+        }
+        self.bytes += text.len();
+        self.chars += text.chars().count();
+        self.newlines += count_newlines(text);
+
+        let (mut cursor, offset) = self.locate(pos);
+        let Some(chunk) = cursor.current() else {
+            // 空表，直接按段接上去
+            for piece in split_pieces(text) {
+                cursor.insert_before(Chunk::new(piece));
+            }
+            return;
+        };
+        let at = byte_offset(&chunk.text, offset);
+        if chunk.text.len() + text.len() <= CHUNK_MAX {
+            chunk.text.insert_str(at, text);
+            chunk.chars += text.chars().count();
+            chunk.newlines += count_newlines(text);
+            return;
+        }
+
+        // 放不下就和原来的块拼起来重新切，第一段留在原地，后面的依次接在后面
+        let joined = [&chunk.text[..at], text, &chunk.text[at..]].concat();
+        let mut pieces = split_pieces(&joined).into_iter();
+        *chunk = Chunk::new(pieces.next().unwrap());
+        for piece in pieces {
+            cursor.insert_after(Chunk::new(piece));
+            cursor.move_next();
+        }
+        // 最后一段可能很短
+        merge_around(&mut cursor);
+    }
+
+    // 区间越界时panic
+    pub fn delete<R: RangeBounds<usize>>(&mut self, range: R) {
+        let (start, end) = self.char_range(range);
+        if start == end {
+            return;
+        }
+        let (mut bytes, mut newlines) = (0, 0);
+        let mut remaining = end - start;
+        let (mut cursor, mut offset) = self.locate(start);
+        while remaining > 0 {
+            let chunk = cursor.current().unwrap();
+            let n = remaining.min(chunk.chars - offset);
+            remaining -= n;
+            if n == chunk.chars {
+                bytes += chunk.text.len();
+                newlines += chunk.newlines;
+                cursor.remove_current();
+                continue;
+            }
+            if n > 0 {
+                let a = byte_offset(&chunk.text, offset);
+                let b = a + byte_offset(&chunk.text[a..], n);
+                bytes += b - a;
+                let nl = count_newlines(&chunk.text[a..b]);
+                newlines += nl;
+                chunk.text.replace_range(a..b, "");
+                chunk.chars -= n;
+                chunk.newlines -= nl;
+            }
+            if remaining == 0 {
+                break;
+            }
+            cursor.move_next();
+            offset = 0;
+        }
+        // cursor停在删除区间的最后一块(被删光时是它后面那块)，前一块是区间开头被截掉尾巴的块
+        merge_around(&mut cursor);
+        self.bytes -= bytes;
+        self.chars -= end - start;
+        self.newlines -= newlines;
+    }
+
+    // 区间越界时panic
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> String {
+        let (start, end) = self.char_range(range);
+        let mut out = String::with_capacity(end - start);
+        let mut pos = 0;
+        for chunk in self.chunks.iter() {
+            if pos >= end {
+                break;
+            }
+            let next = pos + chunk.chars;
+            if next > start {
+                let a = byte_offset(&chunk.text, start.saturating_sub(pos));
+                let b = byte_offset(&chunk.text, end.min(next) - pos);
+                out.push_str(&chunk.text[a..b]);
+            }
+            pos = next;
+        }
+        out
+    }
+
+    // 第pos个字符所在的(行, 列)，都从0开始，列按字符算; pos > len_chars 时panic
+    pub fn line_col(&self, pos: usize) -> (usize, usize) {
+        assert!(pos <= self.chars, "position out of bounds");
+        let (mut line, mut col, mut rest) = (0, 0, pos);
+        for chunk in self.chunks.iter() {
+            if rest <= chunk.chars {
+                for c in chunk.text.chars().take(rest) {
+                    if c == '\n' {
+                        line += 1;
+                        col = 0;
+                    } else {
+                        col += 1;
+                    }
+                }
+                break;
+            }
+            if chunk.newlines > 0 {
+                line += chunk.newlines;
+                col = chunk.text.rsplit('\n').next().unwrap().chars().count();
+            } else {
+                col += chunk.chars;
+            }
+            rest -= chunk.chars;
+        }
+        (line, col)
+    }
+
+    // 第line行开头的字符位置，没有这一行时返回None
+    pub fn line_to_char(&self, line: usize) -> Option<usize> {
+        if line > self.newlines {
+            return None;
+        }
+        let (mut pos, mut need) = (0, line);
+        for chunk in self.chunks.iter() {
+            if need == 0 {
+                break;
+            }
+            if chunk.newlines < need {
+                need -= chunk.newlines;
+                pos += chunk.chars;
+                continue;
+            }
+            for (i, c) in chunk.text.chars().enumerate() {
+                if c == '\n' {
+                    need -= 1;
+                    if need == 0 {
+                        return Some(pos + i + 1);
+                    }
+                }
+            }
+        }
+        Some(pos)
+    }
+
+    // 第pos个字符的字节下标; pos > len_chars 时panic
+    pub fn char_to_byte(&self, pos: usize) -> usize {
+        assert!(pos <= self.chars, "position out of bounds");
+        let (mut bytes, mut rest) = (0, pos);
+        for chunk in self.chunks.iter() {
+            if rest <= chunk.chars {
+                return bytes + byte_offset(&chunk.text, rest);
+            }
+            bytes += chunk.text.len();
+            rest -= chunk.chars;
+        }
+        bytes
+    }
+
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks(self.chunks.iter())
+    }
+
+    // pos所在的块和块内的字符下标; pos正好在两块之间时取前一块的末尾
+    // 空表时cursor停在幽灵位置
+    fn locate(&mut self, pos: usize) -> (CursorMut<'_, Chunk>, usize) {
+        let mut rest = pos;
+        let mut cursor = self.chunks.cursor_front_mut();
+        while let Some(chunk) = cursor.current() {
+            if rest <= chunk.chars {
+                break;
+            }
+            rest -= chunk.chars;
+            cursor.move_next();
+        }
+        (cursor, rest)
+    }
+
+    fn char_range<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        fourth::range_bounds(&range, self.chars)
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(s: &str) -> Self {
+        let mut r = Rope::new();
+        r.insert(0, s);
+        r
+    }
+}
+
+impl Display for Rope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Chunks<'a>(fourth::Iter<'a, Chunk>);
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|c| c.text.as_str())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Chunks<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|c| c.text.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn check(r: &Rope, s: &str) {
+        assert_eq!(r.to_string(), s);
+        assert_eq!(r.len_bytes(), s.len());
+        assert_eq!(r.len_chars(), s.chars().count());
+        assert_eq!(r.len_lines(), s.split('\n').count());
+        assert!(r.chunks().all(|c| !c.is_empty() && c.len() <= CHUNK_MAX));
+    }
+
+    #[test]
+    fn basics() {
+        let mut r = Rope::new();
+        assert!(r.is_empty());
+        assert_eq!(r.line_col(0), (0, 0));
+        r.insert(0, "hello world");
+        r.insert(5, ",");
+        r.insert(0, "你好 ");
+        check(&r, "你好 hello, world");
+        assert_eq!(r.slice(3..8), "hello");
+        assert_eq!(r.slice(..2), "你好");
+        assert_eq!(r.char_to_byte(3), 7);
+
+        r.delete(2..9);
+        check(&r, "你好 world");
+        r.delete(..);
+        check(&r, "");
+        assert_eq!(r.chunks().count(), 0);
+    }
+
+    #[test]
+    fn split_merge_test() {
+        let line = "0123456789abcdefghijklmnopqrstuvwxyz\n";
+        let text = line.repeat(50);
+        let mut r = Rope::from(text.as_str());
+        check(&r, &text);
+        assert!(r.chunks().count() >= text.len() / CHUNK_MAX);
+
+        // 插在块中间导致分裂
+        r.insert(10, &"é".repeat(400));
+        let mut s = text.clone();
+        s.insert_str(10, &"é".repeat(400));
+        check(&r, &s);
+
+        // 跨好几块删除
+        r.delete(5..1500);
+        let a = s.char_indices().nth(5).unwrap().0;
+        let b = s.char_indices().nth(1500).unwrap().0;
+        s.replace_range(a..b, "");
+        check(&r, &s);
+        assert_eq!(r.chunks().collect::<String>(), s);
+        assert!(s.ends_with(r.chunks().next_back().unwrap()));
+    }
+
+    #[test]
+    fn line_col_test() {
+        let text = "ab\ncd\n\n你好x\n".repeat(100);
+        let r = Rope::from(text.as_str());
+        let chars: Vec<char> = text.chars().collect();
+        let (mut line, mut col) = (0, 0);
+        for (pos, c) in chars.iter().enumerate() {
+            assert_eq!(r.line_col(pos), (line, col), "pos {}", pos);
+            if col == 0 {
+                assert_eq!(r.line_to_char(line), Some(pos));
+            }
+            if *c == '\n' {
+                line += 1;
+                col = 0;
+            } else {
+                col += 1;
+            }
+        }
+        assert_eq!(r.line_col(chars.len()), (400, 0));
+        assert_eq!(r.line_to_char(400), Some(chars.len()));
+        assert_eq!(r.line_to_char(401), None);
+        assert_eq!(r.line_to_char(0), Some(0));
+    }
+
+    #[test]
+    fn random_ops_test() {
+        let mut rng = rand::thread_rng();
+        let alphabet: Vec<char> = "abc\nxyz é你".chars().collect();
+        let mut r = Rope::new();
+        let mut s: Vec<char> = Vec::new();
+        for _ in 0..2000 {
+            let pos = rng.gen_range(0..=s.len());
+            if rng.gen_bool(0.6) {
+                let n = rng.gen_range(0..200);
+                let text: String = (0..n)
+                    .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                    .collect();
+                r.insert(pos, &text);
+                s.splice(pos..pos, text.chars());
+            } else {
+                let end = rng.gen_range(pos..=s.len().min(pos + 300));
+                r.delete(pos..end);
+                s.drain(pos..end);
+            }
+        }
+        let s: String = s.into_iter().collect();
+        check(&r, &s);
+        let mid = r.len_chars() / 2;
+        assert_eq!(r.slice(mid..), s.chars().skip(mid).collect::<String>());
+    }
+
+    #[test]
+    fn middle_delete_test() {
+        let text = "abcdefgh".repeat(1000);
+        let mut r = Rope::from(text.as_str());
+        let mut s = text.clone();
+        // 反复在中间删一小段，不能留下一堆碎块
+        while s.len() > 100 {
+            let mid = s.len() / 2;
+            r.delete(mid - 20..mid + 13);
+            s.replace_range(mid - 20..mid + 13, "");
+            check(&r, &s);
+            let chunks: Vec<&str> = r.chunks().collect();
+            assert!(chunks
+                .windows(2)
+                .all(|w| w[0].len() + w[1].len() > CHUNK_MAX
+                    || (w[0].len() >= CHUNK_MIN && w[1].len() >= CHUNK_MIN)));
+        }
+        assert_eq!(r.chunks().count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "range out of bounds")]
+    fn delete_out_of_bounds_test() {
+        let mut r = Rope::from("abc");
+        r.delete(1..4);
+    }
+}
@@ -1,8 +1,18 @@
+use crate::bloom::{self, BloomFilter};
 use rand::{rngs::ThreadRng, Rng};
-use std::{fmt::Display, ptr::NonNull};
+use std::{
+    alloc::{handle_alloc_error, Allocator, Global, Layout},
+    cmp::Ordering,
+    fmt::Display,
+    hash::Hash,
+    marker::PhantomData,
+    mem,
+    ptr::NonNull,
+    time::{Duration, Instant},
+};
 extern crate test;
 
-pub struct SkipList<T>
+pub struct SkipList<T, A: Allocator = Global>
 where
     T: PartialOrd + Default,
 {
@@ -10,180 +20,621 @@ where
     current_level: usize,
     current_len: usize,
     ratio: usize,
-    head: Link<T>,
+    head: Link<T, A>,
     rng: ThreadRng,
 
-    tmp: Vec<Link<T>>,
+    tmp: Vec<Link<T, A>>,
+
+    adaptive: Option<AdaptiveConfig>,
+    access_stats: AccessStats,
+    clock: Box<dyn Clock>,
+    bloom: Option<Bloom<T>>,
+    alloc: A, // 节点和节点的塔都从这里分配
+}
+
+// hash函数在开启时从T: Hash拿到，这样其他方法不用带上Hash约束
+struct Bloom<T> {
+    filter: BloomFilter,
+    hash: fn(&T) -> u64,
+    fp_rate: f64,
+    capacity: usize, // 超过就按两倍重建
+    stale: usize,    // 删掉以后还留在filter里的key数
 }
 
-type Link<T> = Option<NonNull<Node<T>>>;
+type Link<T, A> = Option<NonNull<Node<T, A>>>;
 
-struct Node<T> {
-    next: Vec<Link<T>>,
+// 塔一次留够max_level，之后长层(插入、提升)都不会再分配
+struct Node<T, A: Allocator> {
+    next: Vec<Link<T, A>, A>,
     key: T,
+
+    hits: usize,     // 衰减过的命中次数
+    promoted: usize, // 因为热度额外长出来的层数
+
+    expire_at: Option<Instant>,
+}
+
+impl<T, A: Allocator> Node<T, A> {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expire_at.is_some_and(|t| t <= now)
+    }
+}
+
+// 过期时间都从这里取，测试里可以换成手动拨的钟
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveConfig {
+    pub promote_threshold: usize, // 每多提升一层需要多攒这么多次命中
+    pub decay_interval: usize,    // 每隔多少次get做一次衰减, 0表示不衰减
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AccessStats {
+    pub searches: u64,
+    pub path_len: u64, // 所有查找比较过的节点数之和
+    pub max_path_len: usize,
+    pub promotions: u64,
+    pub demotions: u64,
+    pub bloom_skips: u64, // 被bloom filter直接挡掉、没走查找的get
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkipListStats {
+    pub len: usize,
+    pub current_level: usize,
+    pub level_histogram: Vec<usize>, // [i]是塔高为i+1的节点数
+    pub sampled: usize,
+    pub avg_path_len: f64,
+    pub max_path_len: usize,
+    pub tower_bytes: usize,  // 所有节点(包括head)的next预留的字节数
+    pub slot_occupancy: f64, // 实际用到的指针槽位 / 预留的指针槽位
 }
 
-impl<T: PartialOrd + Display + Default> SkipList<T> {
+impl AccessStats {
+    pub fn avg_path_len(&self) -> f64 {
+        if self.searches == 0 {
+            return 0.0;
+        }
+        self.path_len as f64 / self.searches as f64
+    }
+}
+
+impl<T: PartialOrd + Default> SkipList<T> {
     pub fn new(max_level: usize, ratio: usize) -> Self {
+        Self::with_clock(max_level, ratio, SystemClock)
+    }
+
+    pub fn with_clock(max_level: usize, ratio: usize, clock: impl Clock + 'static) -> Self {
+        Self::with_clock_in(max_level, ratio, clock, Global)
+    }
+
+    // 开启访问偏置: 热点key会被逐层提升，冷key在衰减时降回去
+    pub fn new_adaptive(max_level: usize, ratio: usize, config: AdaptiveConfig) -> Self {
+        let mut l = Self::new(max_level, ratio);
+        l.adaptive = Some(config);
+        l
+    }
+}
+
+impl<T: PartialOrd + Default, A: Allocator + Clone> SkipList<T, A> {
+    pub fn new_in(max_level: usize, ratio: usize, alloc: A) -> Self {
+        Self::with_clock_in(max_level, ratio, SystemClock, alloc)
+    }
+
+    pub fn with_clock_in(
+        max_level: usize,
+        ratio: usize,
+        clock: impl Clock + 'static,
+        alloc: A,
+    ) -> Self {
+        let head = match Self::try_new_node(&alloc, max_level, T::default(), 0, None) {
+            Ok(head) => head,
+            Err(_) => handle_alloc_error(Layout::new::<Node<T, A>>()),
+        };
         Self {
             max_level,
             current_len: 0,
             current_level: 0,
             ratio,
             rng: rand::thread_rng(),
-            head: NonNull::new(Box::into_raw(Box::new(Node {
-                next: Vec::with_capacity(max_level),
-                key: T::default(),
-            }))),
+            head: Some(head),
             tmp: Vec::with_capacity(max_level),
+            adaptive: None,
+            access_stats: AccessStats::default(),
+            clock: Box::new(clock),
+            bloom: None,
+            alloc,
         }
     }
 
-    // TODO: tmp 能不能拿出来，不用mut
-    pub fn get(&mut self, key: T) -> Option<&T> {
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    pub fn access_stats(&self) -> AccessStats {
+        self.access_stats
+    }
+
+    pub fn reset_access_stats(&mut self) {
+        self.access_stats = AccessStats::default();
+    }
+
+    // 从最高层往下找，tmp[i]记录第i层最后一个小于key的节点
+    // 返回第0层的后继(第一个>=key的节点)，以及比较过的节点数
+    // stop_on_hit时在高层碰到key就直接返回，这时只有更高层的tmp是有效的
+    fn search(&mut self, key: &T, stop_on_hit: bool) -> (Link<T, A>, usize) {
         let mut prev = self.head;
         let mut next = None;
+        let mut path_len = 0;
         for i in (0..self.current_level).rev() {
             unsafe {
-                next = prev.and_then(|prev_ptr| (*prev_ptr.as_ptr()).next[i]);
+                next = prev.and_then(|prev_ptr| (&(*prev_ptr.as_ptr()).next)[i]);
                 while let Some(node) = next {
-                    if (*node.as_ptr()).key >= key {
+                    path_len += 1;
+                    if (*node.as_ptr()).key >= *key {
                         break;
                     }
                     prev = next;
-                    next = prev.and_then(|prev_ptr| (*prev_ptr.as_ptr()).next[i]);
+                    next = prev.and_then(|prev_ptr| (&(*prev_ptr.as_ptr()).next)[i]);
                 }
                 self.tmp[i] = prev;
+                if let Some(node) = next {
+                    if stop_on_hit && (*node.as_ptr()).key == *key {
+                        break;
+                    }
+                }
             }
         }
+        (next, path_len)
+    }
 
-        if let Some(next_node) = next {
+    // TODO: tmp 能不能拿出来，不用mut
+    pub fn get(&mut self, key: T) -> Option<&T> {
+        if let Some(b) = &self.bloom {
+            if !b.filter.may_contain_hash((b.hash)(&key)) {
+                self.access_stats.searches += 1;
+                self.access_stats.bloom_skips += 1;
+                return None;
+            }
+        }
+
+        let (next, path_len) = self.search(&key, true);
+
+        self.access_stats.searches += 1;
+        self.access_stats.path_len += path_len as u64;
+        self.access_stats.max_path_len = self.access_stats.max_path_len.max(path_len);
+
+        let node = next.filter(|node| unsafe { (*node.as_ptr()).key == key })?;
+        if unsafe {
+            (*node.as_ptr()).expire_at.is_some() && (*node.as_ptr()).is_expired(self.clock.now())
+        } {
+            return None;
+        }
+        if let Some(config) = self.adaptive {
             unsafe {
-                if (*next_node.as_ptr()).key == key {
-                    return Some(&(*next_node.as_ptr()).key);
-                }
+                (*node.as_ptr()).hits += 1;
             }
-        };
-        None
+            self.try_promote(node, config);
+            if config.decay_interval > 0
+                && self
+                    .access_stats
+                    .searches
+                    .is_multiple_of(config.decay_interval as u64)
+            {
+                self.decay(config);
+            }
+        }
+        unsafe { Some(&(*node.as_ptr()).key) }
     }
 
     pub fn set(&mut self, key: T) {
-        let mut prev = self.head;
-        let mut next = None;
-        for i in (0..self.current_level).rev() {
-            unsafe {
-                next = prev.and_then(|prev_ptr| (*prev_ptr.as_ptr()).next[i]);
-                while let Some(node) = next {
-                    if (*node.as_ptr()).key >= key {
-                        break;
-                    }
-                    prev = next;
-                    next = prev.and_then(|prev_ptr| (*prev_ptr.as_ptr()).next[i]);
-                }
+        self.insert(key, None)
+    }
 
-                self.tmp[i] = prev;
-            }
+    // 分配失败时把key还回来，表不变
+    pub fn try_set(&mut self, key: T) -> Result<(), T> {
+        self.try_insert(key, None)
+    }
+
+    // 过了ttl以后查不到，直到被purge_expired或delete真正删掉
+    // ttl大到Instant表示不了(比如Duration::MAX)时当作永不过期
+    pub fn insert_with_ttl(&mut self, key: T, ttl: Duration) {
+        let expire_at = self.clock.now().checked_add(ttl);
+        self.insert(key, expire_at)
+    }
+
+    fn insert(&mut self, key: T, expire_at: Option<Instant>) {
+        if self.try_insert(key, expire_at).is_err() {
+            handle_alloc_error(Layout::new::<Node<T, A>>())
         }
+    }
+
+    // key已存在时连过期时间一起覆盖
+    fn try_insert(&mut self, key: T, expire_at: Option<Instant>) -> Result<(), T> {
+        let (next, _) = self.search(&key, false);
 
         if let Some(next_node) = next {
             unsafe {
                 if (*next_node.as_ptr()).key == key {
                     (*next_node.as_ptr()).key = key;
-                    return;
+                    (*next_node.as_ptr()).expire_at = expire_at;
+                    return Ok(());
                 }
             }
         };
 
         let level = self.get_random_level();
-        let mut new_node = Node {
-            key,
-            next: Vec::with_capacity(self.max_level),
-        };
+        let node = Self::try_new_node(&self.alloc, self.max_level, key, level, expire_at)?;
 
-        for _ in 0..level {
-            new_node.next.push(None);
+        if let Some(b) = &mut self.bloom {
+            b.filter
+                .insert_hash((b.hash)(unsafe { &(*node.as_ptr()).key }));
+        }
+        while self.current_level < level {
+            self.grow_level();
         }
 
-        let new_node_ptr = NonNull::new(Box::into_raw(Box::new(new_node)));
-        for _ in self.current_level..level {
-            if let Some(node) = self.head {
+        for i in 0..level {
+            if let Some(prev_node) = self.tmp[i].take() {
                 unsafe {
-                    (*node.as_ptr()).next.push(None);
+                    let new_node = &mut *node.as_ptr();
+                    new_node.next[i] = (&(*prev_node.as_ptr()).next)[i];
+                    (&mut (*prev_node.as_ptr()).next)[i] = Some(node);
                 }
-            };
-            self.tmp.push(self.head);
-            self.current_level += 1;
+            }
         }
 
-        println!("level: {} current_level: {}", level, self.current_level);
+        self.current_len += 1;
 
-        for i in 0..level {
-            self.tmp[i].take().map(|prev_node| unsafe {
-                let new_node = &mut *new_node_ptr.unwrap().as_ptr();
-                new_node.next[i] = (*prev_node.as_ptr()).next[i];
-                (*prev_node.as_ptr()).next[i] = new_node_ptr;
-            });
+        if self
+            .bloom
+            .as_ref()
+            .is_some_and(|b| b.filter.len() > b.capacity)
+        {
+            self.rebuild_bloom();
         }
-
-        self.current_len += 1;
+        Ok(())
     }
 
     pub fn delete(&mut self, key: T) -> Option<T> {
+        let (next, _) = self.search(&key, false);
+        if let Some(next_node) = next {
+            unsafe {
+                if (*next_node.as_ptr()).key == key {
+                    let target_node = self.free_node(next_node);
+                    for i in 0..target_node.next.len() {
+                        if let Some(prev_node) = self.tmp[i].take() {
+                            (&mut (*prev_node.as_ptr()).next)[i] = target_node.next[i];
+                        }
+                    }
+                    self.shrink_level();
+                    self.current_len -= 1;
+                    if let Some(b) = &mut self.bloom {
+                        b.stale += 1;
+                    }
+                    if self
+                        .bloom
+                        .as_ref()
+                        .is_some_and(|b| b.stale * 4 > b.filter.len())
+                    {
+                        self.rebuild_bloom();
+                    }
+
+                    if target_node.expire_at.is_some() && target_node.is_expired(self.clock.now()) {
+                        return None;
+                    }
+                    return Some(target_node.key);
+                }
+            }
+        };
+        None
+    }
+
+    // 扫一遍第0层统计结构，再均匀抽sample个key量查找路径
+    pub fn stats(&self, sample: usize) -> SkipListStats {
+        let mut level_histogram = vec![0; self.max_level];
+        let (mut used, mut reserved) = (0, 0);
+        let mut keys = Vec::new();
+        let step = match sample {
+            0 => usize::MAX,
+            _ => self.current_len.div_ceil(sample).max(1),
+        };
+
+        unsafe {
+            let head = &*self.head.unwrap().as_ptr();
+            used += head.next.len();
+            reserved += head.next.capacity();
+
+            let mut cur = head.next.first().copied().flatten();
+            let mut idx = 0;
+            while let Some(node) = cur {
+                let n = &*node.as_ptr();
+                if n.next.len() > level_histogram.len() {
+                    level_histogram.resize(n.next.len(), 0);
+                }
+                level_histogram[n.next.len() - 1] += 1;
+                used += n.next.len();
+                reserved += n.next.capacity();
+                if idx % step == 0 && keys.len() < sample {
+                    keys.push(&n.key);
+                }
+                idx += 1;
+                cur = n.next[0];
+            }
+        }
+
+        let paths: Vec<usize> = keys.iter().map(|key| self.path_len(key)).collect();
+        SkipListStats {
+            len: self.current_len,
+            current_level: self.current_level,
+            level_histogram,
+            sampled: paths.len(),
+            avg_path_len: match paths.len() {
+                0 => 0.0,
+                n => paths.iter().sum::<usize>() as f64 / n as f64,
+            },
+            max_path_len: paths.iter().copied().max().unwrap_or(0),
+            tower_bytes: reserved * mem::size_of::<Link<T, A>>(),
+            slot_occupancy: match reserved {
+                0 => 0.0,
+                r => used as f64 / r as f64,
+            },
+        }
+    }
+
+    // 和get一样的查找路径，但是不写tmp
+    fn path_len(&self, key: &T) -> usize {
         let mut prev = self.head;
-        let mut next = None;
+        let mut path_len = 0;
         for i in (0..self.current_level).rev() {
             unsafe {
-                next = prev.and_then(|prev_ptr| (*prev_ptr.as_ptr()).next[i]);
+                let mut next = prev.and_then(|prev_ptr| (&(*prev_ptr.as_ptr()).next)[i]);
                 while let Some(node) = next {
-                    if (*node.as_ptr()).key >= key {
+                    path_len += 1;
+                    if (*node.as_ptr()).key >= *key {
+                        if (*node.as_ptr()).key == *key {
+                            return path_len;
+                        }
                         break;
                     }
                     prev = next;
-                    next = prev.and_then(|prev_ptr| (*prev_ptr.as_ptr()).next[i]);
+                    next = (&(*node.as_ptr()).next)[i];
                 }
-                self.tmp[i] = prev;
             }
         }
-        if let Some(next_node) = next {
+        path_len
+    }
+
+    // 删掉所有在now之前过期的key，返回删了多少个
+    // 先从高层往下把过期节点摘掉，到第0层时它们已经只剩这一层的引用，可以释放
+    pub fn purge_expired(&mut self, now: Instant) -> usize {
+        let mut purged = 0;
+        for i in (0..self.current_level).rev() {
             unsafe {
-                if (*next_node.as_ptr()).key == key {
-                    let target_node = Box::from_raw(next_node.as_ptr());
-                    for i in 0..target_node.next.len() {
-                        match self.tmp[i].take() {
-                            Some(prev_node) => {
-                                println!(
-                                    "delete prev {} {:?} to next {:?}",
-                                    i, prev_node, target_node.next[i]
-                                );
-                                (*prev_node.as_ptr()).next[i] = target_node.next[i];
-                                if prev_node == self.head.unwrap()
-                                    && (*prev_node.as_ptr()).next[i] == None
-                                {
-                                    self.current_level -= 1;
-                                }
-                            }
-                            None => {
-                                println!(
-                                    "BUG: tmp prev {} is None, but current length is {}",
-                                    i,
-                                    target_node.next.len()
-                                );
-                            }
+                let mut prev = self.head.unwrap();
+                let mut cur = (&(*prev.as_ptr()).next)[i];
+                while let Some(node) = cur {
+                    cur = (&(*node.as_ptr()).next)[i];
+                    if (*node.as_ptr()).is_expired(now) {
+                        (&mut (*prev.as_ptr()).next)[i] = cur;
+                        if i == 0 {
+                            drop(self.free_node(node));
+                            purged += 1;
                         }
+                    } else {
+                        prev = node;
                     }
+                }
+            }
+        }
+        self.shrink_level();
+        self.current_len -= purged;
+        if purged > 0 && self.bloom.is_some() {
+            self.rebuild_bloom();
+        }
+        purged
+    }
 
-                    return Some(target_node.key);
+    // 当前的filter，删掉的key在重建之前还留在里面
+    pub fn bloom(&self) -> Option<&BloomFilter> {
+        self.bloom.as_ref().map(|b| &b.filter)
+    }
+
+    pub fn disable_bloom(&mut self) {
+        self.bloom = None;
+    }
+
+    // 按现有key重新建filter，把删掉的key清出去
+    pub fn rebuild_bloom(&mut self) {
+        let Some(b) = &mut self.bloom else {
+            return;
+        };
+        b.capacity = b.capacity.max(self.current_len * 2);
+        b.filter = BloomFilter::new(b.capacity, b.fp_rate);
+        b.stale = 0;
+        unsafe {
+            let mut cur = (*self.head.unwrap().as_ptr())
+                .next
+                .first()
+                .copied()
+                .flatten();
+            while let Some(node) = cur {
+                b.filter.insert_hash((b.hash)(&(*node.as_ptr()).key));
+                cur = (&(*node.as_ptr()).next)[0];
+            }
+        }
+    }
+
+    // 把没过期的key按顺序写成一个SortedRun，开了bloom时带上只覆盖这些key的filter
+    // encode把一个key追加到buf后面，长度前缀由这里写
+    pub fn flush(&self, mut encode: impl FnMut(&T, &mut Vec<u8>)) -> Vec<u8> {
+        let now = self.clock.now();
+        let mut keys = Vec::with_capacity(self.current_len);
+        unsafe {
+            let mut cur = (*self.head.unwrap().as_ptr())
+                .next
+                .first()
+                .copied()
+                .flatten();
+            while let Some(node) = cur {
+                if !(*node.as_ptr()).is_expired(now) {
+                    keys.push(&(*node.as_ptr()).key);
+                }
+                cur = (&(*node.as_ptr()).next)[0];
+            }
+        }
+
+        let mut buf = RUN_MAGIC.to_vec();
+        let filter = self.bloom.as_ref().map(|b| {
+            let mut f = BloomFilter::new(keys.len(), b.fp_rate);
+            keys.iter().for_each(|k| f.insert_hash((b.hash)(k)));
+            f.to_bytes()
+        });
+        let filter = filter.unwrap_or_default();
+        buf.extend_from_slice(&(filter.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&filter);
+        buf.extend_from_slice(&(keys.len() as u64).to_le_bytes());
+        for k in keys {
+            let at = buf.len();
+            buf.extend_from_slice(&[0; 4]);
+            encode(k, &mut buf);
+            let len = (buf.len() - at - 4) as u32;
+            buf[at..at + 4].copy_from_slice(&len.to_le_bytes());
+        }
+        buf
+    }
+
+    // 在顶上加一层，head的next可能因为之前缩层而已经够长了
+    fn grow_level(&mut self) {
+        let head = self.head.unwrap();
+        unsafe {
+            if (*head.as_ptr()).next.len() == self.current_level {
+                (*head.as_ptr()).next.push(None);
+            }
+        }
+        if self.tmp.len() == self.current_level {
+            self.tmp.push(self.head);
+        } else {
+            self.tmp[self.current_level] = self.head;
+        }
+        self.current_level += 1;
+    }
+
+    // 顶层空了就往下缩
+    fn shrink_level(&mut self) {
+        let head = self.head.unwrap();
+        while self.current_level > 0 {
+            unsafe {
+                if (&(*head.as_ptr()).next)[self.current_level - 1].is_some() {
+                    break;
+                }
+            }
+            self.current_level -= 1;
+        }
+    }
+
+    // 命中次数够了就往上长一层; 调用前必须刚对这个节点的key做过search，tmp里是各层前驱
+    fn try_promote(&mut self, node: NonNull<Node<T, A>>, config: AdaptiveConfig) {
+        unsafe {
+            let n = &mut *node.as_ptr();
+            let height = n.next.len();
+            if height >= self.max_level || n.hits < config.promote_threshold * (n.promoted + 1) {
+                return;
+            }
+            if height == self.current_level {
+                self.grow_level();
+            }
+            let prev = self.tmp[height].unwrap();
+            n.next.push((&(*prev.as_ptr()).next)[height]);
+            (&mut (*prev.as_ptr()).next)[height] = Some(node);
+            n.promoted += 1;
+        }
+        self.access_stats.promotions += 1;
+    }
+
+    // 所有计数减半，然后逐层把不再够热的提升层拆掉
+    fn decay(&mut self, config: AdaptiveConfig) {
+        unsafe {
+            let mut cur = self
+                .head
+                .and_then(|head| (*head.as_ptr()).next.first().copied().flatten());
+            while let Some(node) = cur {
+                (*node.as_ptr()).hits >>= 1;
+                cur = (&(*node.as_ptr()).next)[0];
+            }
+
+            for i in (1..self.current_level).rev() {
+                let mut prev = self.head.unwrap();
+                let mut cur = (&(*prev.as_ptr()).next)[i];
+                while let Some(node) = cur {
+                    let n = &mut *node.as_ptr();
+                    cur = n.next[i];
+                    if n.next.len() == i + 1
+                        && n.promoted > 0
+                        && n.hits < config.promote_threshold * n.promoted
+                    {
+                        (&mut (*prev.as_ptr()).next)[i] = n.next.pop().unwrap();
+                        n.promoted -= 1;
+                        self.access_stats.demotions += 1;
+                    } else {
+                        prev = node;
+                    }
                 }
             }
+        }
+        self.shrink_level();
+    }
+
+    fn try_new_node(
+        alloc: &A,
+        max_level: usize,
+        key: T,
+        level: usize,
+        expire_at: Option<Instant>,
+    ) -> Result<NonNull<Node<T, A>>, T> {
+        let mut next = Vec::new_in(alloc.clone());
+        if next.try_reserve_exact(max_level).is_err() {
+            return Err(key);
+        }
+        next.resize(level, None);
+        let Ok(ptr) = alloc.allocate(Layout::new::<Node<T, A>>()) else {
+            return Err(key);
         };
-        None
+        let node = ptr.cast::<Node<T, A>>();
+        unsafe {
+            node.as_ptr().write(Node {
+                next,
+                key,
+                hits: 0,
+                promoted: 0,
+                expire_at,
+            })
+        };
+        Ok(node)
     }
 
+    // node必须已经从每一层摘下来了
+    unsafe fn free_node(&self, node: NonNull<Node<T, A>>) -> Node<T, A> {
+        *Box::from_raw_in(node.as_ptr(), &self.alloc)
+    }
+
+    // 至少要在第0层，否则节点挂不上去
     fn get_random_level(&mut self) -> usize {
-        let mut l = 0;
-        for _ in 0..self.max_level {
+        let mut l = 1;
+        for _ in 1..self.max_level {
             let gen_v: usize = self.rng.gen();
-            if gen_v % self.ratio == 0 {
+            if gen_v.is_multiple_of(self.ratio) {
                 l += 1;
             }
         }
@@ -191,15 +642,173 @@ impl<T: PartialOrd + Display + Default> SkipList<T> {
     }
 }
 
-impl<T: Default + PartialOrd + Display> Display for SkipList<T> {
+// T的Hash必须和它的PartialEq一致，不然filter会把存在的key挡掉
+impl<T: PartialOrd + Default + Hash, A: Allocator + Clone> SkipList<T, A> {
+    pub fn enable_bloom(&mut self, expected_items: usize, fp_rate: f64) {
+        self.bloom = Some(Bloom {
+            filter: BloomFilter::new(expected_items, fp_rate),
+            hash: bloom::hash::<T>,
+            fp_rate,
+            capacity: expected_items,
+            stale: 0,
+        });
+        self.rebuild_bloom();
+    }
+}
+
+// Vec<u8>/String 这类字节串key，要求T的序和字节序一致
+impl<T: PartialOrd + Default + AsRef<[u8]>, A: Allocator + Clone> SkipList<T, A> {
+    pub fn get_bytes(&self, key: &[u8]) -> Option<&T> {
+        let node = unsafe { &*self.seek_bytes(key)?.as_ptr() };
+        if node.key.as_ref() != key || node.is_expired(self.clock.now()) {
+            return None;
+        }
+        Some(&node.key)
+    }
+
+    // 按key顺序列出所有以prefix开头的key
+    pub fn prefix_scan<'a, 'p>(&'a self, prefix: &'p [u8]) -> PrefixScan<'a, 'p, T, A> {
+        PrefixScan {
+            next: self.seek_bytes(prefix),
+            prefix,
+            now: self.clock.now(),
+            _marker: PhantomData,
+        }
+    }
+
+    // 所有以prefix开头的key的最长公共前缀，没有这样的key就返回None
+    // prefix为空时就是整个表的公共前缀
+    pub fn longest_common_prefix(&self, prefix: &[u8]) -> Option<&[u8]> {
+        let mut iter = self.prefix_scan(prefix);
+        let first = iter.next()?.as_ref();
+        let last = iter.last().map_or(first, |k| k.as_ref());
+        Some(&first[..longest_common_prefix(first, last)])
+    }
+
+    // 找第一个>=key的节点
+    // 节点夹在前驱和后继之间，所以和key至少共享min(lo, hi)个字节，比较时可以跳过
+    fn seek_bytes(&self, key: &[u8]) -> Link<T, A> {
+        let mut prev = self.head;
+        let mut next = None;
+        let (mut lo, mut hi) = (0, 0); // key和前驱/后继的公共前缀长度
+        for i in (0..self.current_level).rev() {
+            unsafe {
+                next = prev.and_then(|prev_ptr| (&(*prev_ptr.as_ptr()).next)[i]);
+                while let Some(node) = next {
+                    let (ord, lcp) = cmp_bytes_from((*node.as_ptr()).key.as_ref(), key, lo.min(hi));
+                    if ord != Ordering::Less {
+                        hi = lcp;
+                        break;
+                    }
+                    lo = lcp;
+                    prev = next;
+                    next = (&(*node.as_ptr()).next)[i];
+                }
+            }
+        }
+        next
+    }
+}
+
+pub fn longest_common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+// 已知a和b前skip个字节相同，从skip开始比较，顺便返回公共前缀长度
+fn cmp_bytes_from(a: &[u8], b: &[u8], skip: usize) -> (Ordering, usize) {
+    let skip = skip.min(a.len()).min(b.len());
+    let lcp = skip + longest_common_prefix(&a[skip..], &b[skip..]);
+    (a[lcp..].cmp(&b[lcp..]), lcp)
+}
+
+// SkipList::flush写出去的有序run，格式(小端):
+// "SRUN" | filter长度 u64(没开bloom时为0) | filter | key个数 u64 | (key长度 u32 | key)...
+const RUN_MAGIC: &[u8; 4] = b"SRUN";
+
+pub struct SortedRun<T> {
+    pub keys: Vec<T>,
+    pub bloom: Option<BloomFilter>,
+}
+
+impl<T> SortedRun<T> {
+    // 格式不对、filter读不回来或者decode失败都返回None
+    pub fn from_bytes(buf: &[u8], mut decode: impl FnMut(&[u8]) -> Option<T>) -> Option<Self> {
+        fn take<'b>(buf: &mut &'b [u8], n: usize) -> Option<&'b [u8]> {
+            let (head, rest) = buf.split_at_checked(n)?;
+            *buf = rest;
+            Some(head)
+        }
+        fn take_u64(buf: &mut &[u8]) -> Option<usize> {
+            usize::try_from(u64::from_le_bytes(take(buf, 8)?.try_into().ok()?)).ok()
+        }
+
+        let mut buf = buf;
+        if take(&mut buf, 4)? != RUN_MAGIC {
+            return None;
+        }
+        let bloom = match take_u64(&mut buf)? {
+            0 => None,
+            n => Some(BloomFilter::from_bytes(take(&mut buf, n)?)?),
+        };
+        let count = take_u64(&mut buf)?;
+        // 每个key至少有4字节的长度前缀，count不可信，先按剩下的字节数封顶
+        let mut keys = Vec::with_capacity(count.min(buf.len() / 4));
+        for _ in 0..count {
+            let len = u32::from_le_bytes(take(&mut buf, 4)?.try_into().ok()?) as usize;
+            keys.push(decode(take(&mut buf, len)?)?);
+        }
+        buf.is_empty().then_some(Self { keys, bloom })
+    }
+}
+
+pub struct PrefixScan<'a, 'p, T, A: Allocator = Global> {
+    next: Link<T, A>,
+    prefix: &'p [u8],
+    now: Instant, // 创建迭代器时的时间，之后过期的照样返回
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: AsRef<[u8]>, A: Allocator> Iterator for PrefixScan<'a, '_, T, A> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = unsafe { &*self.next?.as_ptr() };
+            if !node.key.as_ref().starts_with(self.prefix) {
+                self.next = None;
+                return None;
+            }
+            self.next = node.next[0];
+            if !node.is_expired(self.now) {
+                return Some(&node.key);
+            }
+        }
+    }
+}
+
+// 顺着第0层把节点一个个还给分配器，head最后还
+impl<T: PartialOrd + Default, A: Allocator> Drop for SkipList<T, A> {
+    fn drop(&mut self) {
+        let mut cur = self.head.take();
+        while let Some(node) = cur {
+            unsafe {
+                cur = (*node.as_ptr()).next.first().copied().flatten();
+                drop(Box::from_raw_in(node.as_ptr(), &self.alloc));
+            }
+        }
+    }
+}
+
+impl<T: Default + PartialOrd + Display, A: Allocator> Display for SkipList<T, A> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = String::from("");
         for i in 0..self.current_level {
             unsafe {
-                let mut next = self.head.and_then(|prev_ptr| (*prev_ptr.as_ptr()).next[i]);
+                let mut next = self
+                    .head
+                    .and_then(|prev_ptr| (&(*prev_ptr.as_ptr()).next)[i]);
                 while let Some(node) = next {
                     s += format!("{}=>", (*node.as_ptr()).key).as_str();
-                    next = (*node.as_ptr()).next[i];
+                    next = (&(*node.as_ptr()).next)[i];
                 }
                 s.push('\n');
             }
@@ -212,6 +821,8 @@ impl<T: Default + PartialOrd + Display> Display for SkipList<T> {
 mod tests {
 
     use super::*;
+    use crate::test_util::LimitAlloc;
+    use std::{cell::Cell, rc::Rc};
 
     struct Fib(i32, i32);
 
@@ -222,18 +833,12 @@ mod tests {
         }
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Default)]
     struct TestK {
         k: i32,
         v: i32,
     }
 
-    impl Default for TestK {
-        fn default() -> Self {
-            Self { k: 0, v: 0 }
-        }
-    }
-
     impl PartialEq for TestK {
         fn eq(&self, other: &Self) -> bool {
             self.k == other.k
@@ -278,12 +883,326 @@ mod tests {
         println!("{}", l);
     }
 
+    #[test]
+    fn adaptive_promote_test() {
+        let mut l = SkipList::new_adaptive(
+            16,
+            4,
+            AdaptiveConfig {
+                promote_threshold: 2,
+                decay_interval: 0,
+            },
+        );
+        for i in 0..200 {
+            l.set(i);
+        }
+
+        l.get(150);
+        let cold = l.access_stats();
+        for _ in 0..100 {
+            assert_eq!(l.get(150), Some(&150));
+        }
+        l.reset_access_stats();
+        l.get(150);
+        let hot = l.access_stats();
+
+        assert!(hot.path_len <= cold.path_len, "{:?} {:?}", hot, cold);
+        unsafe {
+            let top = (&(*l.head.unwrap().as_ptr()).next)[l.current_level - 1];
+            assert!(l.current_level == 16 || top.is_some());
+        }
+        assert_eq!(l.get(1000), None);
+    }
+
+    #[test]
+    fn adaptive_decay_test() {
+        let mut l = SkipList::new_adaptive(
+            16,
+            4,
+            AdaptiveConfig {
+                promote_threshold: 1,
+                decay_interval: 8,
+            },
+        );
+        for i in 0..64 {
+            l.set(i);
+        }
+
+        // 先把7捧热，再一直访问别的key让它冷下来
+        for _ in 0..8 {
+            l.get(7);
+        }
+        assert!(l.access_stats().promotions > 0);
+        for i in 0..256 {
+            l.get(20 + i % 40);
+        }
+        assert!(l.access_stats().demotions > 0);
+
+        for i in 0..64 {
+            assert_eq!(l.get(i), Some(&i));
+        }
+        assert_eq!(l.delete(7), Some(7));
+        assert_eq!(l.get(7), None);
+        assert_eq!(l.current_len, 63);
+    }
+
+    #[test]
+    fn prefix_scan_test() {
+        let mut l = SkipList::new(8, 2);
+        for p in [
+            "/usr/bin/ls",
+            "/usr/bin/cat",
+            "/usr/lib/libc.so",
+            "/etc/hosts",
+            "/usr/binary",
+            "/var/log",
+        ] {
+            l.set(p.to_string());
+        }
+
+        let bin: Vec<_> = l.prefix_scan(b"/usr/bin/").collect();
+        assert_eq!(bin, vec!["/usr/bin/cat", "/usr/bin/ls"]);
+        assert_eq!(l.prefix_scan(b"/usr/bin").count(), 3);
+        assert_eq!(l.prefix_scan(b"/opt").count(), 0);
+        assert_eq!(l.prefix_scan(b"").count(), 6);
+
+        assert_eq!(
+            l.get_bytes(b"/etc/hosts").map(|s| s.as_str()),
+            Some("/etc/hosts")
+        );
+        assert_eq!(l.get_bytes(b"/etc"), None);
+    }
+
+    #[test]
+    fn longest_common_prefix_test() {
+        assert_eq!(longest_common_prefix(b"abcd", b"abxy"), 2);
+        assert_eq!(longest_common_prefix(b"ab", b"abc"), 2);
+        assert_eq!(longest_common_prefix(b"", b"abc"), 0);
+
+        let mut l = SkipList::new(8, 2);
+        assert_eq!(l.longest_common_prefix(b""), None);
+        for k in [&b"a/b/c1"[..], b"a/b/c2", b"a/b/d", b"a/x"] {
+            l.set(k.to_vec());
+        }
+        assert_eq!(l.longest_common_prefix(b""), Some(&b"a/"[..]));
+        assert_eq!(l.longest_common_prefix(b"a/b"), Some(&b"a/b/"[..]));
+        assert_eq!(l.longest_common_prefix(b"a/b/c"), Some(&b"a/b/c"[..]));
+        assert_eq!(l.longest_common_prefix(b"a/x"), Some(&b"a/x"[..]));
+        assert_eq!(l.longest_common_prefix(b"b"), None);
+    }
+
+    #[test]
+    fn stats_test() {
+        let mut l = SkipList::new(16, 4);
+        let empty = l.stats(10);
+        assert_eq!(empty.len, 0);
+        assert_eq!(empty.sampled, 0);
+        assert_eq!(empty.max_path_len, 0);
+
+        for i in 0..1000 {
+            l.set(i);
+        }
+        l.delete(500);
+
+        let stats = l.stats(100);
+        assert_eq!(stats.len, 999);
+        assert_eq!(stats.level_histogram.iter().sum::<usize>(), 999);
+        assert_eq!(stats.level_histogram.len(), 16);
+        assert_eq!(stats.sampled, 100);
+        assert!(stats.max_path_len as f64 >= stats.avg_path_len);
+        assert!(stats.avg_path_len >= 1.0);
+        assert!(stats.slot_occupancy > 0.0 && stats.slot_occupancy <= 1.0);
+        // 每个节点都按max_level预留了next
+        assert_eq!(
+            stats.tower_bytes,
+            1000 * 16 * mem::size_of::<Link<i32, Global>>()
+        );
+
+        assert_eq!(l.stats(0).sampled, 0);
+        assert_eq!(l.stats(5000).sampled, 999);
+    }
+
+    struct ManualClock(Rc<Cell<Instant>>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn ttl_test() {
+        let now = Rc::new(Cell::new(Instant::now()));
+        let mut l = SkipList::with_clock(8, 2, ManualClock(now.clone()));
+        l.set(1);
+        l.insert_with_ttl(2, Duration::from_secs(10));
+        l.insert_with_ttl(3, Duration::from_secs(20));
+        assert_eq!(l.get(2), Some(&2));
+
+        now.set(now.get() + Duration::from_secs(10));
+        assert_eq!(l.get(1), Some(&1));
+        assert_eq!(l.get(2), None);
+        assert_eq!(l.get(3), Some(&3));
+
+        // 重新set会清掉过期时间
+        l.set(3);
+        now.set(now.get() + Duration::from_secs(60));
+        assert_eq!(l.get(3), Some(&3));
+        assert_eq!(l.current_len, 3);
+        assert_eq!(l.delete(2), None);
+        assert_eq!(l.current_len, 2);
+
+        l.insert_with_ttl(4, Duration::MAX);
+        now.set(now.get() + Duration::from_secs(1 << 40));
+        assert_eq!(l.get(4), Some(&4));
+        assert_eq!(l.purge_expired(now.get()), 0);
+    }
+
+    #[test]
+    fn purge_expired_test() {
+        let now = Rc::new(Cell::new(Instant::now()));
+        let mut l = SkipList::with_clock(16, 4, ManualClock(now.clone()));
+        for i in 0..100 {
+            l.insert_with_ttl(i, Duration::from_secs(i as u64 % 3));
+        }
+        let t = now.get() + Duration::from_secs(1);
+        now.set(t);
+        assert_eq!(l.purge_expired(t), 67);
+        assert_eq!(l.current_len, 33);
+        assert_eq!(l.stats(0).level_histogram.iter().sum::<usize>(), 33);
+        for i in 0..100 {
+            let expect = if i % 3 == 2 { Some(&i) } else { None };
+            assert_eq!(l.get(i), expect);
+        }
+        assert_eq!(l.purge_expired(t), 0);
+        assert_eq!(l.purge_expired(t + Duration::from_secs(2)), 33);
+        assert_eq!(l.current_level, 0);
+    }
+
+    #[test]
+    fn prefix_scan_ttl_test() {
+        let now = Rc::new(Cell::new(Instant::now()));
+        let mut l = SkipList::with_clock(8, 2, ManualClock(now.clone()));
+        l.set(b"a/1".to_vec());
+        l.insert_with_ttl(b"a/2".to_vec(), Duration::from_secs(1));
+        l.set(b"a/3".to_vec());
+        now.set(now.get() + Duration::from_secs(1));
+        assert_eq!(l.prefix_scan(b"a/").count(), 2);
+        assert_eq!(l.get_bytes(b"a/2"), None);
+    }
+
+    #[test]
+    fn bloom_test() {
+        let mut l = SkipList::new(16, 4);
+        for i in 0..50 {
+            l.set(i * 2);
+        }
+        l.enable_bloom(100, 0.01);
+        for i in 50..200 {
+            l.set(i * 2);
+        }
+        // 超过容量以后重建过
+        assert!(l.bloom().unwrap().len() >= 200);
+
+        for i in 0..200 {
+            assert_eq!(l.get(i * 2), Some(&(i * 2)));
+        }
+        l.reset_access_stats();
+        for i in 0..200 {
+            assert_eq!(l.get(i * 2 + 1), None);
+        }
+        assert!(l.access_stats().bloom_skips > 150);
+
+        for i in 0..100 {
+            l.delete(i * 2);
+        }
+        assert!(l.bloom().unwrap().len() < 200);
+        for i in 0..100 {
+            assert_eq!(l.get(i * 2), None);
+            assert_eq!(l.get(200 + i * 2), Some(&(200 + i * 2)));
+        }
+
+        let bytes = l.bloom().unwrap().to_bytes();
+        let flushed = BloomFilter::from_bytes(&bytes).unwrap();
+        assert!(flushed.may_contain(&398));
+
+        l.disable_bloom();
+        assert!(l.bloom().is_none());
+        assert_eq!(l.get(398), Some(&398));
+    }
+
+    #[test]
+    fn flush_test() {
+        let now = Rc::new(Cell::new(Instant::now()));
+        let mut l = SkipList::with_clock(8, 2, ManualClock(now.clone()));
+        let encode = |k: &i32, buf: &mut Vec<u8>| buf.extend_from_slice(&k.to_le_bytes());
+        let decode = |b: &[u8]| Some(i32::from_le_bytes(b.try_into().ok()?));
+
+        let run = SortedRun::from_bytes(&l.flush(encode), decode).unwrap();
+        assert!(run.keys.is_empty());
+        assert!(run.bloom.is_none());
+
+        for i in (0..100).rev() {
+            l.set(i * 3);
+        }
+        l.insert_with_ttl(1000, Duration::from_secs(1));
+        l.enable_bloom(50, 0.01);
+        l.delete(0);
+        now.set(now.get() + Duration::from_secs(1));
+
+        let bytes = l.flush(encode);
+        let run = SortedRun::from_bytes(&bytes, decode).unwrap();
+        // 删掉的和过期的都不写出去
+        assert_eq!(run.keys, (1..100).map(|i| i * 3).collect::<Vec<_>>());
+        let bloom = run.bloom.unwrap();
+        assert_eq!(bloom.len(), 99);
+        assert!(run.keys.iter().all(|k| bloom.may_contain(k)));
+        assert!(!bloom.may_contain(&1000));
+
+        assert!(SortedRun::from_bytes(&bytes[..bytes.len() - 1], decode).is_none());
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(SortedRun::from_bytes(&longer, decode).is_none());
+        assert!(SortedRun::<i32>::from_bytes(&bytes, |_| None).is_none());
+    }
+
+    #[test]
+    fn allocator_test() {
+        let alloc = LimitAlloc::new(8);
+        // 每个节点两块: 节点本身和它的塔
+        let mut l = SkipList::new_in(8, 2, &alloc);
+        assert_eq!(alloc.live(), 2);
+        for i in 0..3 {
+            assert_eq!(l.try_set(i), Ok(()));
+        }
+        assert_eq!(alloc.live(), 8);
+        assert_eq!(l.try_set(3), Err(3));
+        assert_eq!(alloc.live(), 8);
+        // 已有的key只覆盖，不分配
+        assert_eq!(l.try_set(1), Ok(()));
+        assert_eq!(l.get(3), None);
+
+        assert_eq!(l.delete(1), Some(1));
+        assert_eq!(alloc.live(), 6);
+        assert_eq!(l.try_set(3), Ok(()));
+        assert_eq!(l.get(3), Some(&3));
+        assert!(std::ptr::eq(*l.allocator(), &alloc));
+
+        assert_eq!(l.delete(0), Some(0));
+        l.insert_with_ttl(4, Duration::ZERO);
+        assert_eq!(alloc.live(), 8);
+        assert_eq!(l.purge_expired(Instant::now()), 1);
+        assert_eq!(alloc.live(), 6);
+        drop(l);
+        assert_eq!(alloc.live(), 0);
+    }
+
     //test skiplist::tests::delete_bench ... bench:           2 ns/iter (+/- 0)
     //test skiplist::tests::get_bench    ... bench:           8 ns/iter (+/- 0)
     //test skiplist::tests::set_bench    ... bench:      85,710 ns/iter (+/- 5,533)
     #[bench]
     fn set_bench(b: &mut test::Bencher) {
-        let mut f = Fib(0, 1).into_iter();
+        let mut f = Fib(0, 1);
         let mut l = SkipList::new(32, 4);
 
         b.iter(|| {
@@ -296,13 +1215,13 @@ mod tests {
 
     #[bench]
     fn get_bench(b: &mut test::Bencher) {
-        let mut f = Fib(0, 1).into_iter();
+        let mut f = Fib(0, 1);
         let l = &mut SkipList::new(32, 4);
         for _ in 0..10000 {
             let v = f.next().unwrap();
             l.set(TestK { k: v, v });
         }
-        let mut f = Fib(0, 1).into_iter();
+        let mut f = Fib(0, 1);
         b.iter(move || {
             let v = f.next().unwrap();
             l.get(TestK { k: v, v });
@@ -311,7 +1230,7 @@ mod tests {
 
     #[bench]
     fn delete_bench(b: &mut test::Bencher) {
-        let mut f = Fib(0, 1).into_iter();
+        let mut f = Fib(0, 1);
         let l = &mut SkipList::new(32, 4);
         for _ in 0..10000 {
             let v = f.next().unwrap();
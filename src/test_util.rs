@@ -0,0 +1,41 @@
+// 测试共用的小工具，只在cfg(test)下编译
+
+use std::{
+    alloc::{AllocError, Allocator, Global, Layout},
+    cell::Cell,
+    ptr::NonNull,
+};
+
+// 最多同时分配limit块，记录还有多少块没还
+pub(crate) struct LimitAlloc {
+    limit: usize,
+    live: Cell<usize>,
+}
+
+impl LimitAlloc {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            live: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn live(&self) -> usize {
+        self.live.get()
+    }
+}
+
+unsafe impl Allocator for LimitAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.live.get() == self.limit {
+            return Err(AllocError);
+        }
+        self.live.set(self.live.get() + 1);
+        Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.live.set(self.live.get() - 1);
+        Global.deallocate(ptr, layout)
+    }
+}
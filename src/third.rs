@@ -1,13 +1,18 @@
 // Rc 可变的情况下, 实现双端队列
 
+use crate::fourth::OverflowPolicy;
 use std::{
     cell::{Ref, RefCell, RefMut},
+    mem,
     rc::Rc,
 };
 
 pub struct List<T> {
     head: Link<T>,
     tail: Link<T>,
+    length: usize,
+    capacity: usize, // 不限容量时是usize::MAX
+    policy: OverflowPolicy,
 }
 
 type Link<T> = Option<Rc<RefCell<Node<T>>>>;
@@ -33,11 +38,109 @@ impl<T> List<T> {
         Self {
             head: None,
             tail: None,
+            length: 0,
+            capacity: usize::MAX,
+            policy: OverflowPolicy::Reject,
         }
     }
 
+    // 最多放capacity个元素，满了以后按policy处理，语义和fourth::List一样
+    pub fn bounded(capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        let mut l = Self::new();
+        l.capacity = capacity;
+        l.policy = policy;
+        l
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    // 不限容量时返回None
+    pub fn capacity(&self) -> Option<usize> {
+        (self.capacity != usize::MAX).then_some(self.capacity)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.length >= self.capacity
+    }
+
+    // 满了且策略是Reject时panic; 按策略挤掉的元素直接drop
     pub fn push_front(&mut self, elem: T) {
-        let new_head = Node::new(elem);
+        assert!(self.try_push_front(elem).is_ok(), "list is full");
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        assert!(self.try_push_back(elem).is_ok(), "list is full");
+    }
+
+    // 满了(Reject)时把elem还回来; 按策略挤掉了另一头的元素时把它返回，调用方可以回收或者记下来
+    pub fn try_push_front(&mut self, elem: T) -> Result<Option<T>, T> {
+        let (node, old) = self.node_for_push(elem, true)?;
+        self.push_front_node(node);
+        Ok(old)
+    }
+
+    pub fn try_push_back(&mut self, elem: T) -> Result<Option<T>, T> {
+        let (node, old) = self.node_for_push(elem, false)?;
+        self.push_back_node(node);
+        Ok(old)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        // into_inner确实能消费refcell，但rc不允许move内部的结构
+        // 所以需要先释放rc
+        // unwrap需要debug trait
+        // 或者使用ok将result转成option（不需要关心error）
+        // 这里没有程序bug的话node的引用就是就是1
+        self.pop_front_node()
+            .map(|node| Rc::try_unwrap(node).ok().unwrap().into_inner().elem)
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.pop_back_node()
+            .map(|node| Rc::try_unwrap(node).ok().unwrap().into_inner().elem)
+    }
+
+    // 满了以后按策略腾位置，顺便返回被挤掉的元素; OverwriteOldest直接把另一头的节点拿来复用
+    #[allow(clippy::type_complexity)]
+    fn node_for_push(
+        &mut self,
+        elem: T,
+        front: bool,
+    ) -> Result<(Rc<RefCell<Node<T>>>, Option<T>), T> {
+        if !self.is_full() {
+            return Ok((Node::new(elem), None));
+        }
+        match self.policy {
+            OverflowPolicy::Reject => Err(elem),
+            OverflowPolicy::EvictOpposite => {
+                let old = if front {
+                    self.pop_back()
+                } else {
+                    self.pop_front()
+                };
+                Ok((Node::new(elem), old))
+            }
+            OverflowPolicy::OverwriteOldest => {
+                let node = if front {
+                    self.pop_back_node()
+                } else {
+                    self.pop_front_node()
+                }
+                .unwrap();
+                let old = mem::replace(&mut node.borrow_mut().elem, elem);
+                Ok((node, Some(old)))
+            }
+        }
+    }
+
+    fn push_front_node(&mut self, new_head: Rc<RefCell<Node<T>>>) {
         match self.head.take() {
             Some(old_head) => {
                 old_head.borrow_mut().prev = Some(new_head.clone()); // 手动deref，否则rc和refcell都有borrowmut，会调用错误
@@ -49,10 +152,10 @@ impl<T> List<T> {
                 self.tail = Some(new_head);
             }
         }
+        self.length += 1;
     }
 
-    pub fn push_back(&mut self, elem: T) {
-        let new_tail = Node::new(elem);
+    fn push_back_node(&mut self, new_tail: Rc<RefCell<Node<T>>>) {
         match self.tail.take() {
             Some(old_tail) => {
                 old_tail.borrow_mut().next = Some(new_tail.clone());
@@ -64,43 +167,41 @@ impl<T> List<T> {
                 self.head = Some(new_tail);
             }
         }
+        self.length += 1;
     }
-    pub fn pop_front(&mut self) -> Option<T> {
+
+    // 摘下来的节点前后指针都清空了，外面只剩这一个rc
+    fn pop_front_node(&mut self) -> Option<Rc<RefCell<Node<T>>>> {
         // Trick：将原来head中的值全部take
-        self.head.take().map(|node| {
-            match node.borrow_mut().next.take() {
-                Some(next) => {
-                    next.borrow_mut().prev.take();
-                    self.head = Some(next);
-                }
-                None => {
-                    // 这是头里有值，但头的next没值的情况
-                    // 取出后变成空表，所以tail也要take
-                    self.tail.take();
-                }
+        let node = self.head.take()?;
+        match node.borrow_mut().next.take() {
+            Some(next) => {
+                next.borrow_mut().prev.take();
+                self.head = Some(next);
             }
-            // into_inner确实能消费refcell，但rc不允许move内部的结构
-            // 所以需要先释放rc
-            // unwrap需要debug trait
-            // 或者使用ok将result转成option（不需要关心error）
-            // 这里没有程序bug的话node的引用就是就是1
-            Rc::try_unwrap(node).ok().unwrap().into_inner().elem
-        })
+            None => {
+                // 这是头里有值，但头的next没值的情况
+                // 取出后变成空表，所以tail也要take
+                self.tail.take();
+            }
+        }
+        self.length -= 1;
+        Some(node)
     }
 
-    pub fn pop_back(&mut self) -> Option<T> {
-        self.tail.take().map(|node| {
-            match node.borrow_mut().prev.take() {
-                Some(prev) => {
-                    prev.borrow_mut().next.take();
-                    self.tail = Some(prev);
-                }
-                None => {
-                    self.head.take();
-                }
+    fn pop_back_node(&mut self) -> Option<Rc<RefCell<Node<T>>>> {
+        let node = self.tail.take()?;
+        match node.borrow_mut().prev.take() {
+            Some(prev) => {
+                prev.borrow_mut().next.take();
+                self.tail = Some(prev);
+            }
+            None => {
+                self.head.take();
             }
-            Rc::try_unwrap(node).ok().unwrap().into_inner().elem
-        })
+        }
+        self.length -= 1;
+        Some(node)
     }
 
     pub fn peek_front(&self) -> Option<Ref<T>> {
@@ -176,6 +277,7 @@ impl<'a, T> Iterator for Iter<'a, T> {
 #[cfg(test)]
 mod test {
     use super::List;
+    use crate::fourth::OverflowPolicy;
     #[test]
     fn basics() {
         let mut list = List::new();
@@ -248,4 +350,51 @@ mod test {
         assert_eq!(&*list.peek_back().unwrap(), &1);
         assert_eq!(&mut *list.peek_back_mut().unwrap(), &mut 1);
     }
+
+    #[test]
+    fn bounded() {
+        let mut list = List::bounded(2, OverflowPolicy::Reject);
+        assert_eq!(List::<i32>::new().capacity(), None);
+        assert_eq!(list.capacity(), Some(2));
+        list.push_back(1);
+        list.push_back(2);
+        assert!(list.is_full());
+        assert_eq!(list.try_push_back(3), Err(3));
+        assert_eq!(list.try_push_front(0), Err(0));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.try_push_front(0), Ok(None));
+        assert_eq!(list.len(), 2);
+
+        let mut list = List::bounded(2, OverflowPolicy::EvictOpposite);
+        for i in 0..4 {
+            list.push_back(i);
+        }
+        assert_eq!(list.try_push_back(4), Ok(Some(2)));
+        list.push_front(2);
+        assert_eq!(list.pop_front(), Some(2));
+        assert_eq!(list.pop_front(), Some(3));
+        assert_eq!(list.pop_front(), None);
+
+        let mut list = List::bounded(3, OverflowPolicy::OverwriteOldest);
+        for i in 0..10u32 {
+            // 挤掉的是三个之前放进去的
+            assert_eq!(list.try_push_front(i), Ok(i.checked_sub(3)));
+        }
+        assert_eq!(list.len(), 3);
+        assert_eq!(&*list.peek_front().unwrap(), &9);
+        assert_eq!(&*list.peek_back().unwrap(), &7);
+        list.push_back(6);
+        assert_eq!(list.pop_back(), Some(6));
+        assert_eq!(list.pop_back(), Some(7));
+        assert_eq!(list.pop_back(), Some(8));
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "list is full")]
+    fn bounded_push_full() {
+        let mut list = List::bounded(1, OverflowPolicy::Reject);
+        list.push_front(1);
+        list.push_front(2);
+    }
 }